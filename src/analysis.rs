@@ -0,0 +1,302 @@
+use crate::cli::{Args, WindowFunction};
+use crate::window::{self, Selection};
+use rtrb::{Producer, RingBuffer};
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const POLL: Duration = Duration::from_millis(2);
+
+/// Where the audio callback puts what it played, for the analysis thread.
+pub struct Feed {
+    heard: Producer<f32>,
+}
+
+impl Feed {
+    /// Queues as much of `samples` as fits and drops the rest, rather than
+    /// blocking the audio thread.
+    pub fn push(&mut self, samples: &[f32]) {
+        let n = self.heard.slots().min(samples.len());
+        if let Ok(chunk) = self.heard.write_chunk_uninit(n) {
+            chunk.fill_from_iter(samples.iter().copied());
+        }
+    }
+}
+
+/// The latest magnitude spectrum of one channel: `fft_size / 2 + 1` bins,
+/// scaled so a full-scale sine reads 1.0.
+#[derive(Clone)]
+pub struct Spectrum {
+    magnitudes: Arc<Mutex<Vec<f32>>>,
+}
+
+impl Spectrum {
+    fn new(fft_size: usize) -> Self {
+        Spectrum {
+            magnitudes: Arc::new(Mutex::new(vec![0.0; fft_size / 2 + 1])),
+        }
+    }
+
+    pub fn read(&self) -> Vec<f32> {
+        self.magnitudes.lock().unwrap().clone()
+    }
+
+    /// Silences the spectrum until the next one is analysed.
+    pub fn clear(&self) {
+        self.magnitudes.lock().unwrap().fill(0.0);
+    }
+}
+
+/// Windows interleaved frames and transforms each channel.
+struct Stft {
+    fft: Arc<dyn Fft<f32>>,
+    buffer: Vec<Complex<f32>>,
+    channels: usize,
+    window: Selection,
+    kaiser_beta: f64,
+    /// Coefficients for the last window function used.
+    shape: Option<(WindowFunction, Vec<f32>)>,
+}
+
+impl Stft {
+    fn new(fft_size: usize, channels: usize, window: Selection, kaiser_beta: f64) -> Self {
+        Stft {
+            fft: FftPlanner::new().plan_fft_forward(fft_size),
+            buffer: vec![Complex::default(); fft_size],
+            channels,
+            window,
+            kaiser_beta,
+            shape: None,
+        }
+    }
+
+    fn analyse(&mut self, frame: &[f32], spectra: &[Spectrum]) {
+        let fft_size = self.buffer.len();
+        let function = self.window.get();
+        let coefficients = match &mut self.shape {
+            Some((current, coefficients)) if *current == function => coefficients,
+            shape => {
+                let coefficients = window::coefficients(function, fft_size, self.kaiser_beta);
+                &mut shape.insert((function, coefficients)).1
+            }
+        };
+
+        for (channel, spectrum) in spectra.iter().enumerate() {
+            let samples = frame.iter().skip(channel).step_by(self.channels);
+            for ((bin, sample), w) in self.buffer.iter_mut().zip(samples).zip(coefficients.iter()) {
+                *bin = Complex::new(sample * w, 0.0);
+            }
+            self.fft.process(&mut self.buffer);
+
+            let mut magnitudes = spectrum.magnitudes.lock().unwrap();
+            for (k, (magnitude, bin)) in magnitudes.iter_mut().zip(&self.buffer).enumerate() {
+                // Every bin but DC and Nyquist holds half of a real sine.
+                let scale = if k == 0 || k == fft_size / 2 {
+                    1.0
+                } else {
+                    2.0
+                };
+                *magnitude = bin.norm() * scale / fft_size as f32;
+            }
+        }
+    }
+}
+
+/// The last `--fft-size` frames heard, interleaved, which calls for a spectrum
+/// every `--hop` frames.
+struct History {
+    samples: Vec<f32>,
+    /// Samples in one FFT's worth of frames.
+    frame_len: usize,
+    hop: usize,
+    channels: usize,
+    /// Frames heard since the last spectrum was due.
+    fresh: usize,
+}
+
+impl History {
+    fn new(fft_size: usize, hop: usize, channels: usize) -> Self {
+        let frame_len = fft_size * channels;
+        History {
+            // Trimmed to one frame after each spectrum, so at most one frame
+            // plus two hops long when read a hop at a time.
+            samples: Vec::with_capacity(frame_len + 2 * hop * channels),
+            frame_len,
+            hop,
+            channels,
+            fresh: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.samples.clear();
+        self.fresh = 0;
+    }
+
+    /// Adds whole interleaved frames and hands `analyse` each frame that
+    /// falls due, once there is a full one.
+    fn push(&mut self, samples: impl IntoIterator<Item = f32>, mut analyse: impl FnMut(&[f32])) {
+        let before = self.samples.len();
+        self.samples.extend(samples);
+        self.fresh += (self.samples.len() - before) / self.channels;
+        // Nothing due, so nothing to trim either.
+        if self.fresh < self.hop {
+            return;
+        }
+
+        while self.fresh >= self.hop {
+            // The frame ending one hop after the last one.
+            let end = self.samples.len() - (self.fresh - self.hop) * self.channels;
+            self.fresh -= self.hop;
+            if end >= self.frame_len {
+                analyse(&self.samples[end - self.frame_len..end]);
+            }
+        }
+
+        // Keep only what the next frame could overlap.
+        let excess = self.samples.len().saturating_sub(self.frame_len);
+        self.samples.drain(..excess);
+    }
+}
+
+/// Starts a thread that keeps the last `--fft-size` frames of what the
+/// callback played and, every `--hop` frames, windows them and updates each
+/// channel's spectrum. Everything heard is forgotten each time `flushes`
+/// goes up, so a seek isn't drawn mixed with what came before it.
+/// Returns the feed for the callback and the spectra.
+pub fn spawn(
+    args: &Args,
+    channels: usize,
+    window: Selection,
+    flushes: Arc<AtomicU64>,
+) -> (Feed, Vec<Spectrum>) {
+    let (fft_size, hop) = (args.fft_size(), args.hop());
+    let capacity = fft_size.max(args.buffer_size) * channels * 4;
+    let (heard, mut r_heard) = RingBuffer::new(capacity);
+
+    let spectra: Vec<_> = (0..channels).map(|_| Spectrum::new(fft_size)).collect();
+    let outputs = spectra.clone();
+    let mut stft = Stft::new(fft_size, channels, window, args.kaiser_beta);
+    let mut history = History::new(fft_size, hop, channels);
+    let mut flushed = flushes.load(Ordering::Acquire);
+
+    thread::spawn(move || loop {
+        let flushes = flushes.load(Ordering::Acquire);
+        if flushes != flushed {
+            flushed = flushes;
+            history.clear();
+            // Whatever is queued was played before the flush.
+            if let Ok(chunk) = r_heard.read_chunk(r_heard.slots()) {
+                chunk.commit_all();
+            }
+            for spectrum in &outputs {
+                spectrum.clear();
+            }
+        }
+
+        // Reading a hop at a time leaves at most one spectrum due per read.
+        let n = r_heard.slots().min(hop * channels) / channels * channels;
+        if n == 0 {
+            if r_heard.is_abandoned() {
+                return;
+            }
+            thread::sleep(POLL);
+            continue;
+        }
+
+        if let Ok(chunk) = r_heard.read_chunk(n) {
+            history.push(chunk, |frame| stft.analyse(frame, &outputs));
+        }
+    });
+
+    (Feed { heard }, spectra)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pushes `frames` frames a few at a time, never more than a hop, and
+    /// returns every frame analysed.
+    fn run(history: &mut History, frames: usize) -> Vec<Vec<f32>> {
+        let channels = history.channels;
+        let mut analysed = vec![];
+        let mut pushed = 0;
+        for size in [1, 2, 2, 1, 1].into_iter().cycle() {
+            if pushed == frames {
+                break;
+            }
+            let size = size.min(frames - pushed);
+            // Frame i holds i in the first channel and -i in the second.
+            let samples = (pushed..pushed + size)
+                .flat_map(|i| [i as f32, -(i as f32)].into_iter().take(channels));
+            history.push(samples, |frame| analysed.push(frame.to_vec()));
+            pushed += size;
+            assert!(history.samples.len() <= history.frame_len + 2 * history.hop * channels);
+        }
+        analysed
+    }
+
+    #[test]
+    fn a_spectrum_is_due_every_hop_once_there_is_a_full_frame() {
+        let mut history = History::new(8, 2, 1);
+        let analysed = run(&mut history, 41);
+        // Frames end on every hop from the 8th frame to the 40th.
+        assert_eq!(analysed.len(), 17);
+        for (k, frame) in analysed.iter().enumerate() {
+            let end = 8 + 2 * k;
+            let expected: Vec<f32> = (end - 8..end).map(|i| i as f32).collect();
+            assert_eq!(*frame, expected);
+        }
+    }
+
+    #[test]
+    fn a_flush_forgets_what_was_heard() {
+        use clap::Parser;
+        use std::time::Instant;
+
+        let args = Args::try_parse_from([
+            "visualizer",
+            "--stdin",
+            "--sample-rate=48000",
+            "--fft-size=256",
+            "--hop=64",
+        ])
+        .unwrap();
+        let flushes = Arc::new(AtomicU64::new(0));
+        let window = Selection::new(WindowFunction::Hann);
+        let (mut feed, spectra) = spawn(&args, 1, window, Arc::clone(&flushes));
+        let loudest = || spectra[0].read().into_iter().fold(0.0, f32::max);
+        let wait_for = |done: &dyn Fn() -> bool| {
+            let start = Instant::now();
+            while !done() {
+                assert!(start.elapsed() < Duration::from_secs(5));
+                thread::sleep(POLL);
+            }
+        };
+
+        let sine: Vec<f32> = (0..256).map(|n| (n as f32 * 0.3).sin()).collect();
+        feed.push(&sine);
+        wait_for(&|| loudest() > 0.5);
+
+        flushes.fetch_add(1, Ordering::Release);
+        wait_for(&|| loudest() == 0.0);
+        // Half a frame after the flush isn't enough for a spectrum on its own.
+        feed.push(&sine[..128]);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(loudest(), 0.0);
+    }
+
+    #[test]
+    fn frames_stay_whole_across_channels() {
+        let mut history = History::new(8, 2, 2);
+        let analysed = run(&mut history, 20);
+        assert_eq!(analysed.len(), 7);
+        let last = analysed.last().unwrap();
+        let expected: Vec<f32> = (12..20).flat_map(|i| [i as f32, -(i as f32)]).collect();
+        assert_eq!(*last, expected);
+    }
+}
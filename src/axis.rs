@@ -0,0 +1,180 @@
+use crate::cli::{Aggregate, Args, Scale};
+
+/// The FFT bins one bar is made from.
+struct Band {
+    bins: std::ops::Range<usize>,
+}
+
+/// Maps FFT bins onto the bars drawn across the screen: evenly spaced on a
+/// linear, logarithmic, Mel or Bark frequency scale, or one per 1/N octave,
+/// between two frequencies.
+pub struct Axis {
+    bands: Vec<Band>,
+    aggregate: Aggregate,
+    low_hz: f64,
+    high_hz: f64,
+}
+
+impl Axis {
+    pub fn new(args: &Args) -> Self {
+        let fft_size = args.fft_size();
+        let bin_hz = args.sample_rate / fft_size as f64;
+        let nyquist = args.sample_rate / 2.0;
+        let high_hz = args.max_freq.min(nyquist);
+        let low_hz = args.min_freq.min(high_hz);
+
+        let edges = match args.scale {
+            Scale::Octave => octave_edges(args.octave_fraction, low_hz, high_hz),
+            scale => {
+                let (low, high) = (warp(scale, low_hz), warp(scale, high_hz));
+                (0..=args.bins)
+                    .map(|i| unwarp(scale, low + (high - low) * i as f64 / args.bins as f64))
+                    .collect()
+            }
+        };
+
+        let bin_count = fft_size / 2 + 1;
+        let bands = edges
+            .windows(2)
+            .map(|edge| {
+                let low = ((edge[0] / bin_hz).ceil() as usize).min(bin_count - 1);
+                let high = ((edge[1] / bin_hz).ceil() as usize).min(bin_count);
+                if low < high {
+                    Band { bins: low..high }
+                } else {
+                    // Narrower than a bin: take whichever bin is closest to its middle.
+                    let middle = (warp(args.scale, edge[0]) + warp(args.scale, edge[1])) / 2.0;
+                    let center = unwarp(args.scale, middle);
+                    let bin = ((center / bin_hz).round() as usize).min(bin_count - 1);
+                    Band { bins: bin..bin + 1 }
+                }
+            })
+            .collect();
+
+        Axis {
+            bands,
+            aggregate: args.aggregate,
+            low_hz,
+            high_hz,
+        }
+    }
+
+    /// How many bars there are per channel.
+    pub fn bars(&self) -> usize {
+        self.bands.len()
+    }
+
+    /// The lowest and highest frequencies shown, in Hz.
+    pub fn range(&self) -> (f64, f64) {
+        (self.low_hz, self.high_hz)
+    }
+
+    /// One value per bar from a channel's magnitudes, either the loudest bin
+    /// in the band or the root mean square, which averages their power.
+    pub fn map(&self, magnitudes: &[f32]) -> Vec<f32> {
+        self.bands
+            .iter()
+            .map(|band| {
+                let bins = &magnitudes[band.bins.clone()];
+                match self.aggregate {
+                    Aggregate::Max => bins.iter().copied().fold(0.0, f32::max),
+                    Aggregate::Power => {
+                        let power: f32 = bins.iter().map(|m| m * m).sum();
+                        (power / bins.len() as f32).sqrt()
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Where `hz` falls on `scale`, in units that are evenly spaced on screen.
+fn warp(scale: Scale, hz: f64) -> f64 {
+    match scale {
+        Scale::Linear => hz,
+        Scale::Log | Scale::Octave => hz.max(f64::MIN_POSITIVE).ln(),
+        Scale::Mel => 2595.0 * (1.0 + hz / 700.0).log10(),
+        // Traunmüller's approximation.
+        Scale::Bark => 26.81 * hz / (1960.0 + hz) - 0.53,
+    }
+}
+
+fn unwarp(scale: Scale, position: f64) -> f64 {
+    match scale {
+        Scale::Linear => position,
+        Scale::Log | Scale::Octave => position.exp(),
+        Scale::Mel => 700.0 * (10f64.powf(position / 2595.0) - 1.0),
+        Scale::Bark => 1960.0 * (position + 0.53) / (26.28 - position),
+    }
+}
+
+/// Edges of the 1/`fraction` octave bands centred on 1 kHz and its steps
+/// up and down, keeping those that lie within the range.
+fn octave_edges(fraction: usize, low_hz: f64, high_hz: f64) -> Vec<f64> {
+    let step = 2f64.powf(1.0 / fraction as f64);
+    let half_step = step.sqrt();
+    let first = (low_hz * half_step / 1000.0).log(step).ceil() as i32;
+    let last = (high_hz / half_step / 1000.0).log(step).floor() as i32;
+    (first..=last + 1)
+        .map(|band| 1000.0 * step.powi(band) / half_step)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn axis(flags: &[&str]) -> Axis {
+        let args = ["visualizer", "--stdin", "--sample-rate=48000"]
+            .iter()
+            .chain(flags);
+        Axis::new(&Args::try_parse_from(args).unwrap())
+    }
+
+    #[test]
+    fn octave_bands_step_by_the_fraction_around_1khz() {
+        let edges = octave_edges(3, 20.0, 20000.0);
+        let step = 2f64.powf(1.0 / 3.0);
+        for edge in edges.windows(2) {
+            assert!((edge[1] / edge[0] - step).abs() < 1e-9);
+        }
+        assert!(edges[0] >= 20.0 && edges[edges.len() - 1] <= 20000.0);
+        // 1 kHz is the middle of a band, not an edge.
+        let centres: Vec<_> = edges.windows(2).map(|e| (e[0] * e[1]).sqrt()).collect();
+        assert!(centres.iter().any(|centre| (centre - 1000.0).abs() < 1e-6));
+        // The last whole band below 20 kHz is centred on 16 kHz.
+        assert_eq!(centres.len(), 29);
+        assert!((centres[28] - 16000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn bands_narrower_than_a_bin_take_the_nearest_one() {
+        // 10 Hz bands over bins 46.875 Hz apart.
+        let axis = axis(&[
+            "--fft-size=1024",
+            "--scale=linear",
+            "--min-freq=0",
+            "--max-freq=100",
+            "--bins=10",
+        ]);
+        let magnitudes: Vec<f32> = (0..513).map(|bin| bin as f32).collect();
+        assert_eq!(
+            axis.map(&magnitudes),
+            [0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0, 2.0, 2.0, 2.0]
+        );
+    }
+
+    #[test]
+    fn warping_and_unwarping_gives_the_frequency_back() {
+        for scale in [Scale::Linear, Scale::Log, Scale::Mel, Scale::Bark] {
+            for hz in [20.0, 100.0, 1000.0, 8000.0, 20000.0] {
+                let back = unwarp(scale, warp(scale, hz));
+                assert!(
+                    (back - hz).abs() < hz * 1e-9,
+                    "{scale:?} {hz} came back as {back}"
+                );
+            }
+        }
+    }
+}
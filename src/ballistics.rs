@@ -0,0 +1,145 @@
+use crate::cli::Args;
+use std::time::Instant;
+
+/// Smooths each band's level like a meter: it rises with the attack time and
+/// falls with the release time, measured in seconds rather than frames so it
+/// moves the same however fast the window redraws. Peaks are held for a while
+/// and then fall at a fixed number of decibels per second.
+pub struct Ballistics {
+    attack: f32,
+    release: f32,
+    hold: f32,
+    fall_db: f32,
+    levels: Vec<f32>,
+    /// The highest recent level of each band.
+    peaks: Vec<f32>,
+    /// Seconds each peak has been held.
+    held: Vec<f32>,
+    last: Option<Instant>,
+}
+
+impl Ballistics {
+    pub fn new(args: &Args) -> Self {
+        Ballistics {
+            attack: args.attack_ms / 1000.0,
+            release: args.release_ms / 1000.0,
+            hold: args.peak_hold_ms / 1000.0,
+            fall_db: args.peak_fall_db,
+            levels: vec![],
+            peaks: vec![],
+            held: vec![],
+            last: None,
+        }
+    }
+
+    /// Moves the levels and peaks towards `values` by the time since the last update.
+    pub fn update(&mut self, values: &[f32]) {
+        let now = Instant::now();
+        let dt = self
+            .last
+            .map_or(0.0, |last| now.duration_since(last).as_secs_f32());
+        self.last = Some(now);
+        self.advance(values, dt);
+    }
+
+    /// Moves the levels and peaks towards `values` by `dt` seconds.
+    fn advance(&mut self, values: &[f32], dt: f32) {
+        if self.levels.len() != values.len() {
+            self.levels = values.to_vec();
+            self.peaks = vec![0.0; values.len()];
+            self.held = vec![0.0; values.len()];
+        }
+
+        let bands = self
+            .levels
+            .iter_mut()
+            .zip(&mut self.peaks)
+            .zip(&mut self.held);
+        for (((level, peak), held), &value) in bands.zip(values) {
+            let time = if value > *level {
+                self.attack
+            } else {
+                self.release
+            };
+            // Closes 63% of the gap in `time`.
+            let step = if time > 0.0 {
+                1.0 - (-dt / time).exp()
+            } else {
+                1.0
+            };
+            *level += (value - *level) * step;
+
+            if *level >= *peak {
+                *peak = *level;
+                *held = 0.0;
+                continue;
+            }
+            let falling = (*held + dt - self.hold).clamp(0.0, dt);
+            *held += dt;
+            *peak = (*peak * 10f32.powf(-self.fall_db * falling / 20.0)).max(*level);
+        }
+    }
+
+    pub fn levels(&self) -> &[f32] {
+        &self.levels
+    }
+
+    pub fn peaks(&self) -> &[f32] {
+        &self.peaks
+    }
+
+    /// Drops everything back to silence straight away.
+    pub fn reset(&mut self) {
+        self.levels.fill(0.0);
+        self.peaks.fill(0.0);
+        self.held.fill(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ballistics() -> Ballistics {
+        Ballistics {
+            attack: 0.01,
+            release: 0.1,
+            hold: 0.5,
+            fall_db: 20.0,
+            levels: vec![],
+            peaks: vec![],
+            held: vec![],
+            last: None,
+        }
+    }
+
+    #[test]
+    fn levels_close_most_of_the_gap_in_the_attack_or_release_time() {
+        let mut ballistics = ballistics();
+        ballistics.advance(&[0.0], 0.0);
+        ballistics.advance(&[1.0], 0.01);
+        let attacked = ballistics.levels()[0];
+        assert!((attacked - (1.0 - (-1f32).exp())).abs() < 1e-6);
+
+        ballistics.advance(&[1.0], 10.0);
+        ballistics.advance(&[0.0], 0.1);
+        assert!((ballistics.levels()[0] - (-1f32).exp()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn peaks_hold_then_fall_at_the_set_rate() {
+        let mut ballistics = ballistics();
+        ballistics.advance(&[1.0], 0.0);
+        ballistics.advance(&[0.0], 0.25);
+        ballistics.advance(&[0.0], 0.25);
+        assert_eq!(ballistics.peaks(), [1.0]);
+
+        // Held for 0.5s, then half a second at 20 dB per second.
+        ballistics.advance(&[0.0], 0.5);
+        assert!((ballistics.peaks()[0] - 10f32.powf(-0.5)).abs() < 1e-6);
+
+        // Never below the level itself.
+        ballistics.advance(&[0.5], 10.0);
+        assert_eq!(ballistics.peaks(), ballistics.levels());
+    }
+}
@@ -0,0 +1,79 @@
+use crate::error::Error;
+use crate::ring::AudioWriter;
+#[cfg(feature = "portaudio")]
+use crate::ring::Stamp;
+#[cfg(feature = "portaudio")]
+use portaudio as pa;
+
+/// A running input stream, which stops when dropped.
+pub struct Capture {
+    #[cfg(feature = "portaudio")]
+    _stream: pa::Stream<pa::NonBlocking, pa::Input<f32>>,
+}
+
+/// The number of channels to capture from the default input device, at most stereo.
+#[cfg(feature = "portaudio")]
+pub fn channels() -> Result<usize, Error> {
+    let pa = pa::PortAudio::new()?;
+    let def_input = pa.default_input_device()?;
+    let input_info = pa.device_info(def_input)?;
+    Ok(input_info.max_input_channels.clamp(1, 2) as usize)
+}
+
+/// Opens the default input device and queues every captured block of
+/// interleaved frames on `audio`, where the output callback picks it up.
+#[cfg(feature = "portaudio")]
+pub fn start(
+    sample_rate: f64,
+    buffer_size: usize,
+    channels: usize,
+    mut audio: AudioWriter,
+) -> Result<Capture, Error> {
+    let pa = pa::PortAudio::new()?;
+    let def_input = pa.default_input_device()?;
+    let input_info = pa.device_info(def_input)?;
+    let latency = input_info.default_low_input_latency;
+    let input_params = pa::StreamParameters::new(def_input, channels as i32, true, latency);
+    let input_settings =
+        pa::InputStreamSettings::new(input_params, sample_rate, buffer_size as u32);
+
+    let mut position = 0;
+    let mut stream = pa.open_non_blocking_stream(
+        input_settings,
+        move |pa::InputStreamCallbackArgs { buffer, frames, .. }| {
+            position += frames as u64;
+            // Drop the block rather than stall the input thread if playback falls behind.
+            let stamp = Stamp {
+                end: position,
+                track: 0,
+                playlist: 0,
+                last: false,
+            };
+            audio.write(buffer, stamp);
+            pa::Continue
+        },
+    )?;
+    stream.start()?;
+
+    Ok(Capture { _stream: stream })
+}
+
+#[cfg(not(feature = "portaudio"))]
+pub fn channels() -> Result<usize, Error> {
+    Err(unsupported())
+}
+
+#[cfg(not(feature = "portaudio"))]
+pub fn start(
+    _sample_rate: f64,
+    _buffer_size: usize,
+    _channels: usize,
+    _audio: AudioWriter,
+) -> Result<Capture, Error> {
+    Err(unsupported())
+}
+
+#[cfg(not(feature = "portaudio"))]
+fn unsupported() -> Error {
+    Error::Args("--capture needs a build with the portaudio feature".to_string())
+}
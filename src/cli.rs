@@ -0,0 +1,474 @@
+use crate::input;
+use crate::session::Session;
+use crate::transport::{MAX_SPEED, MIN_SPEED};
+use clap::{Parser, ValueEnum};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum View {
+    /// One set of bars per channel, side by side
+    Split,
+    /// A single set of bars averaged across channels
+    Mix,
+}
+
+/// What each block is multiplied by before the FFT, trading frequency
+/// resolution against leakage from loud bins into quiet ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum WindowFunction {
+    /// No window: the narrowest peaks, but the most leakage
+    Rectangular,
+    Hann,
+    Hamming,
+    /// Very low leakage, for quiet detail next to loud peaks
+    BlackmanHarris,
+    /// Accurate peak levels, with wide peaks
+    FlatTop,
+    /// Tunable with --kaiser-beta
+    Kaiser,
+}
+
+/// How frequencies are spread across the screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Scale {
+    /// Equal widths in Hz
+    Linear,
+    /// Equal widths per octave
+    Log,
+    /// One bar per 1/N octave band, N from --octave-fraction
+    Octave,
+    /// Equal steps in perceived pitch
+    Mel,
+    /// Equal steps along the ear's critical bands
+    Bark,
+}
+
+/// How the FFT bins within one bar are combined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Aggregate {
+    /// The loudest bin
+    Max,
+    /// The average power of the bins
+    Power,
+}
+
+/// How bar heights follow the level of each band.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Magnitude {
+    /// In proportion to amplitude
+    Linear,
+    /// In decibels, so quiet detail shows alongside loud peaks
+    Db,
+    /// In proportion to power, which favours the loudest bands
+    Power,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum RawFormat {
+    /// Unsigned 8-bit
+    U8,
+    /// Signed 16-bit little-endian
+    S16le,
+    /// Signed 24-bit little-endian, packed in 3 bytes
+    S24le,
+    /// Signed 32-bit little-endian
+    S32le,
+    /// 32-bit float little-endian
+    F32le,
+    /// 64-bit float little-endian
+    F64le,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    /// No sound device: audio is consumed in real time and thrown away
+    Null,
+    /// PortAudio, through its default output device
+    #[cfg(feature = "portaudio")]
+    Portaudio,
+    /// cpal, the library rodio plays through
+    #[cfg(feature = "cpal")]
+    Cpal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OnEnd {
+    /// Stay on the last frame, silent, until a seek
+    Stop,
+    /// Start again from the beginning without a gap
+    Loop,
+    /// Close the window
+    Exit,
+    /// Move on to the next input without a gap, stopping after the last
+    Next,
+}
+
+/// How much buffering the output device is asked for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Latency {
+    /// The device's suggested latency for interactive use
+    Low,
+    /// The device's suggested latency for robust playback
+    High,
+    Seconds(f64),
+}
+
+impl FromStr for Latency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "low" => Ok(Latency::Low),
+            "high" => Ok(Latency::High),
+            _ => match s.parse::<f64>() {
+                Ok(seconds) if seconds > 0.0 => Ok(Latency::Seconds(seconds)),
+                _ => Err("expected low, high, or a number of seconds".to_string()),
+            },
+        }
+    }
+}
+
+#[cfg(feature = "portaudio")]
+const DEFAULT_BACKEND: Backend = Backend::Portaudio;
+#[cfg(all(feature = "cpal", not(feature = "portaudio")))]
+const DEFAULT_BACKEND: Backend = Backend::Cpal;
+#[cfg(not(any(feature = "portaudio", feature = "cpal")))]
+const DEFAULT_BACKEND: Backend = Backend::Null;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "weresocool_visualizer",
+    about = "Play an audio file or composition and draw its spectrum"
+)]
+pub struct Args {
+    /// Wav, FLAC, Ogg Vorbis or MP3 files, .socool compositions, or directories of them, to play in turn
+    #[arg(
+        value_name = "INPUT",
+        required_unless_present_any = ["capture", "stdin", "list_devices"]
+    )]
+    pub inputs: Vec<PathBuf>,
+
+    /// Frames per audio callback
+    #[arg(long, default_value_t = 1024 * 4)]
+    pub buffer_size: usize,
+
+    /// Frames per FFT [default: --buffer-size]
+    #[arg(long)]
+    pub fft_size: Option<usize>,
+
+    /// Frames between the starts of successive FFTs [default: a quarter of --fft-size]
+    #[arg(long)]
+    pub hop: Option<usize>,
+
+    /// Number of bars drawn per channel, except with --scale octave
+    #[arg(long, default_value_t = 1024 * 4 / 20)]
+    pub bins: usize,
+
+    /// How frequencies are spread across the bars
+    #[arg(long, value_enum, default_value_t = Scale::Log)]
+    pub scale: Scale,
+
+    /// Bands per octave with --scale octave: 1, 3, 6, 12 and so on
+    #[arg(long, default_value_t = 3)]
+    pub octave_fraction: usize,
+
+    /// How the FFT bins within a bar are combined
+    #[arg(long, value_enum, default_value_t = Aggregate::Max)]
+    pub aggregate: Aggregate,
+
+    /// Lowest frequency shown, in Hz
+    #[arg(long, default_value_t = 20.0)]
+    pub min_freq: f64,
+
+    /// Highest frequency shown, in Hz, up to half the sample rate
+    #[arg(long, default_value_t = 20000.0)]
+    pub max_freq: f64,
+
+    /// Width of the pixel buffer
+    #[arg(long, default_value_t = 2048)]
+    pub width: u32,
+
+    /// Height of the pixel buffer
+    #[arg(long, default_value_t = 1024)]
+    pub height: u32,
+
+    /// Sample rate of the output stream in Hz
+    #[arg(long, default_value_t = 48000.0)]
+    pub sample_rate: f64,
+
+    /// Which audio library plays the output
+    #[arg(long, value_enum, default_value_t = DEFAULT_BACKEND)]
+    pub backend: Backend,
+
+    /// Print the PortAudio host APIs and devices, then exit
+    #[arg(long)]
+    pub list_devices: bool,
+
+    /// PortAudio output device, by index from --list-devices or by name
+    #[arg(long)]
+    pub device: Option<String>,
+
+    /// PortAudio output latency: low, high, or a number of seconds
+    #[arg(long, default_value = "low")]
+    pub latency: Latency,
+
+    /// With --backend null, pull audio as fast as it can be read instead of in real time
+    #[arg(long)]
+    pub offline: bool,
+
+    /// How bar heights follow level; M cycles through them while running
+    #[arg(long, value_enum, default_value_t = Magnitude::Db)]
+    pub magnitude: Magnitude,
+
+    /// Level in dBFS at the bottom of the bars
+    #[arg(long, default_value_t = -90.0, allow_negative_numbers = true)]
+    pub floor_db: f32,
+
+    /// Level in dBFS at the top of the bars
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub ceiling_db: f32,
+
+    /// Milliseconds for a bar to rise most of the way to a louder level
+    #[arg(long, default_value_t = 10.0)]
+    pub attack_ms: f32,
+
+    /// Milliseconds for a bar to fall most of the way to a quieter level
+    #[arg(long, default_value_t = 300.0)]
+    pub release_ms: f32,
+
+    /// Milliseconds a peak line stays put before falling
+    #[arg(long, default_value_t = 1000.0)]
+    pub peak_hold_ms: f32,
+
+    /// Decibels per second a peak line falls once its hold is over
+    #[arg(long, default_value_t = 20.0)]
+    pub peak_fall_db: f32,
+
+    /// Window function applied before the FFT; W cycles through them while running
+    #[arg(long, value_enum, default_value_t = WindowFunction::Hann)]
+    pub window: WindowFunction,
+
+    /// Shape of the Kaiser window: 0 is rectangular, higher trades resolution for less leakage
+    #[arg(long, default_value_t = 8.6)]
+    pub kaiser_beta: f64,
+
+    /// How channels are laid out on screen
+    #[arg(long, value_enum, default_value_t = View::Split)]
+    pub view: View,
+
+    /// Seconds skipped by the left and right arrow keys
+    #[arg(long, default_value_t = 5.0)]
+    pub seek_step: f64,
+
+    /// Seconds skipped by the up and down arrow keys
+    #[arg(long, default_value_t = 30.0)]
+    pub long_seek_step: f64,
+
+    /// Playback speed, from 0.25 to 2
+    #[arg(long, default_value_t = 1.0)]
+    pub speed: f64,
+
+    /// Keep the pitch when playing faster or slower, instead of varispeed
+    #[arg(long)]
+    pub preserve_pitch: bool,
+
+    /// File the loop points are saved in [default: each input path with .session appended]
+    #[arg(long)]
+    pub session: Option<PathBuf>,
+
+    /// What to do when the input runs out
+    #[arg(long, value_enum, default_value_t = OnEnd::Stop)]
+    pub on_end: OnEnd,
+
+    /// Reload the input every time it is rewritten: a .socool composition is
+    /// re-rendered, and a wav is reloaded once the render writing it finishes
+    #[arg(long)]
+    pub watch: bool,
+
+    /// With --watch, start a reloaded input from the beginning instead of the playhead
+    #[arg(long, requires = "watch")]
+    pub reload_from_start: bool,
+
+    /// Visualize the default input device instead of a file
+    #[arg(long, conflicts_with = "inputs")]
+    pub capture: bool,
+
+    /// Play captured input through the output device
+    #[arg(long)]
+    pub monitor: bool,
+
+    /// Read raw interleaved PCM from stdin instead of a file
+    #[arg(long, conflicts_with_all = ["inputs", "capture"])]
+    pub stdin: bool,
+
+    /// Sample rate of the raw input in Hz
+    #[arg(
+        long,
+        requires = "stdin",
+        conflicts_with_all = ["inputs", "capture"],
+        default_value_t = 48000
+    )]
+    pub rate: u32,
+
+    /// Number of interleaved channels in the raw input
+    #[arg(
+        long,
+        requires = "stdin",
+        conflicts_with_all = ["inputs", "capture"],
+        default_value_t = 2
+    )]
+    pub channels: usize,
+
+    /// Sample format of the raw input
+    #[arg(
+        long,
+        requires = "stdin",
+        conflicts_with_all = ["inputs", "capture"],
+        value_enum,
+        default_value_t = RawFormat::F32le
+    )]
+    pub format: RawFormat,
+}
+
+impl Args {
+    pub fn validate(&self) -> Result<(), String> {
+        for input in &self.inputs {
+            if !input.is_file() && !input.is_dir() {
+                return Err(format!("input {} does not exist", input.display()));
+            }
+        }
+        if self.buffer_size == 0 {
+            return Err("--buffer-size must be greater than 0".to_string());
+        }
+        let fft_size = self.fft_size();
+        if fft_size < 8 {
+            return Err("--fft-size must be at least 8".to_string());
+        }
+        if !(1..=fft_size).contains(&self.hop()) {
+            return Err(format!(
+                "--hop must be between 1 and the FFT size, {fft_size}"
+            ));
+        }
+        if self.attack_ms < 0.0 || self.release_ms < 0.0 || self.peak_hold_ms < 0.0 {
+            return Err(
+                "--attack-ms, --release-ms and --peak-hold-ms can't be negative".to_string(),
+            );
+        }
+        if self.peak_fall_db <= 0.0 {
+            return Err("--peak-fall-db must be greater than 0".to_string());
+        }
+        if self.floor_db >= self.ceiling_db {
+            return Err("--floor-db must be below --ceiling-db".to_string());
+        }
+        if self.bins == 0 {
+            return Err("--bins must be greater than 0".to_string());
+        }
+        if self.octave_fraction == 0 {
+            return Err("--octave-fraction must be greater than 0".to_string());
+        }
+        if self.min_freq < 0.0 || self.min_freq >= self.max_freq {
+            return Err("--min-freq must be at least 0 and below --max-freq".to_string());
+        }
+        if self.min_freq == 0.0 && matches!(self.scale, Scale::Log | Scale::Octave) {
+            return Err("--min-freq must be above 0 on a logarithmic scale".to_string());
+        }
+        if self.width < 2 || self.height < 2 {
+            return Err("--width and --height must be at least 2".to_string());
+        }
+        if !(0.0..=50.0).contains(&self.kaiser_beta) {
+            return Err("--kaiser-beta must be between 0 and 50".to_string());
+        }
+        if self.sample_rate <= 0.0 {
+            return Err("--sample-rate must be greater than 0".to_string());
+        }
+        if self.seek_step <= 0.0 || self.long_seek_step <= 0.0 {
+            return Err("--seek-step and --long-seek-step must be greater than 0".to_string());
+        }
+        if !(MIN_SPEED..=MAX_SPEED).contains(&self.speed) {
+            return Err(format!(
+                "--speed must be between {MIN_SPEED} and {MAX_SPEED}"
+            ));
+        }
+        if self.offline && self.backend != Backend::Null {
+            return Err("--offline only applies with --backend null".to_string());
+        }
+        if (self.device.is_some() || self.latency != Latency::Low) && !self.uses_portaudio() {
+            return Err("--device and --latency only apply to --backend portaudio".to_string());
+        }
+        if self.offline && self.capture {
+            return Err(
+                "--offline can't be used with --capture, which runs in real time".to_string(),
+            );
+        }
+        if self.monitor && !self.capture {
+            return Err("--monitor only applies with --capture".to_string());
+        }
+        if self.stdin && (self.rate == 0 || self.channels == 0) {
+            return Err("--rate and --channels must be greater than 0".to_string());
+        }
+        if self.stdin && self.on_end == OnEnd::Loop {
+            return Err("--on-end loop can't rewind --stdin".to_string());
+        }
+        if self.watch && !matches!(self.inputs.as_slice(), [input] if input::can_watch(input)) {
+            return Err("--watch needs a single .socool composition or .wav file".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn fft_size(&self) -> usize {
+        self.fft_size.unwrap_or(self.buffer_size)
+    }
+
+    /// 75% overlap unless set.
+    pub fn hop(&self) -> usize {
+        self.hop.unwrap_or(self.fft_size() / 4).max(1)
+    }
+
+    fn uses_portaudio(&self) -> bool {
+        #[cfg(feature = "portaudio")]
+        return self.backend == Backend::Portaudio;
+        #[cfg(not(feature = "portaudio"))]
+        return false;
+    }
+
+    /// Where the loop points for `track` are kept.
+    pub fn session_path(&self, track: Option<&Path>) -> Option<PathBuf> {
+        match (&self.session, track) {
+            (Some(session), _) => Some(session.clone()),
+            (None, Some(track)) => Some(Session::path_for(track)),
+            (None, None) => None,
+        }
+    }
+
+    pub fn logical_size(&self) -> (u32, u32) {
+        (self.width / 2, self.height / 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_format_flags_need_stdin() {
+        for flag in ["--rate=44100", "--channels=1", "--format=s16le"] {
+            assert!(Args::try_parse_from(["visualizer", flag, "song.wav"]).is_err());
+            assert!(Args::try_parse_from(["visualizer", flag, "--capture"]).is_err());
+            assert!(Args::try_parse_from(["visualizer", flag, "--list-devices"]).is_err());
+            assert!(Args::try_parse_from(["visualizer", "--stdin", flag]).is_ok());
+        }
+        assert!(Args::try_parse_from(["visualizer", "song.wav"]).is_ok());
+    }
+
+    #[test]
+    fn stdin_cant_loop_at_the_end() {
+        let validate = |on_end| {
+            Args::try_parse_from(["visualizer", "--stdin", on_end])
+                .unwrap()
+                .validate()
+        };
+        assert!(validate("--on-end=loop").is_err());
+        assert!(validate("--on-end=stop").is_ok());
+    }
+}
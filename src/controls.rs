@@ -0,0 +1,53 @@
+use crate::cli::Magnitude;
+use crate::levels::Levels;
+use crate::window::{self, Selection};
+use clap::ValueEnum;
+use winit::event::VirtualKeyCode;
+use winit_input_helper::WinitInputHelper;
+
+/// Keyboard control of the analysis: W steps through the window functions
+/// and M through the ways bar heights follow level.
+pub struct Controls {
+    window: Selection,
+    levels: Levels,
+    /// Only show the settings once they have been touched.
+    changed: bool,
+}
+
+impl Controls {
+    pub fn new(window: Selection, levels: Levels) -> Self {
+        Controls {
+            window,
+            levels,
+            changed: false,
+        }
+    }
+
+    pub fn levels(&self) -> &Levels {
+        &self.levels
+    }
+
+    /// A line describing the analysis settings, once any have been changed.
+    pub fn status(&self) -> Option<String> {
+        self.changed.then(|| {
+            format!(
+                "WINDOW {}   {}",
+                window::name(self.window.get()).to_uppercase(),
+                self.levels.describe()
+            )
+        })
+    }
+
+    pub fn handle_input(&mut self, input: &WinitInputHelper) {
+        if input.key_pressed(VirtualKeyCode::W) {
+            self.window.next();
+            self.changed = true;
+        }
+        if input.key_pressed(VirtualKeyCode::M) {
+            let modes = Magnitude::value_variants();
+            let current = modes.iter().position(|&m| m == self.levels.mode);
+            self.levels.mode = modes[current.map_or(0, |i| (i + 1) % modes.len())];
+            self.changed = true;
+        }
+    }
+}
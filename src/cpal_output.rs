@@ -0,0 +1,96 @@
+use crate::error::Error;
+use crate::output::{AudioOutput, Render};
+use rodio::cpal::{
+    self,
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, SampleFormat, SizedSample,
+};
+
+/// Plays through the default output device of cpal's default host, which
+/// needs no native libraries beyond the platform's own.
+pub struct CpalOutput {
+    device: cpal::Device,
+    config: cpal::StreamConfig,
+    sample_format: SampleFormat,
+    buffer_size: usize,
+    stream: Option<cpal::Stream>,
+}
+
+impl CpalOutput {
+    pub fn new(sample_rate: f64, buffer_size: usize) -> Result<Self, Error> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| Error::Device("no default output device".to_string()))?;
+        let default_config = device.default_output_config()?;
+        let config = cpal::StreamConfig {
+            channels: default_config.channels(),
+            sample_rate: cpal::SampleRate(sample_rate as u32),
+            buffer_size: cpal::BufferSize::Default,
+        };
+
+        Ok(CpalOutput {
+            device,
+            config,
+            sample_format: default_config.sample_format(),
+            buffer_size,
+            stream: None,
+        })
+    }
+
+    fn build<T>(&self, mut render: Render) -> Result<cpal::Stream, Error>
+    where
+        T: SizedSample + FromSample<f32>,
+    {
+        let channels = self.config.channels as usize;
+        // Rendered in stereo, then spread over however many channels the device has.
+        let mut stereo = vec![0.0; self.buffer_size * 2];
+
+        let stream = self.device.build_output_stream(
+            &self.config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                for chunk in data.chunks_mut(stereo.len() / 2 * channels) {
+                    let stereo = &mut stereo[..chunk.len() / channels * 2];
+                    render(stereo);
+
+                    for (frame, lr) in chunk.chunks_exact_mut(channels).zip(stereo.chunks_exact(2))
+                    {
+                        match frame {
+                            [mono] => *mono = T::from_sample((lr[0] + lr[1]) / 2.0),
+                            [left, right, rest @ ..] => {
+                                *left = T::from_sample(lr[0]);
+                                *right = T::from_sample(lr[1]);
+                                rest.fill(T::EQUILIBRIUM);
+                            }
+                            [] => {}
+                        }
+                    }
+                }
+            },
+            |err| log::error!("audio output error: {err}"),
+            None,
+        )?;
+        Ok(stream)
+    }
+}
+
+impl AudioOutput for CpalOutput {
+    fn start(&mut self, render: Render) -> Result<(), Error> {
+        let stream = match self.sample_format {
+            SampleFormat::F32 => self.build::<f32>(render)?,
+            SampleFormat::F64 => self.build::<f64>(render)?,
+            SampleFormat::I16 => self.build::<i16>(render)?,
+            SampleFormat::I32 => self.build::<i32>(render)?,
+            SampleFormat::U16 => self.build::<u16>(render)?,
+            SampleFormat::U8 => self.build::<u8>(render)?,
+            other => {
+                return Err(Error::Device(format!(
+                    "unsupported output sample format {other}"
+                )))
+            }
+        };
+        stream.play()?;
+
+        self.stream = Some(stream);
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use crate::error::Error;
+use crate::source::Source;
+use rodio::Decoder;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+pub fn is_compressed(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "flac" | "ogg" | "oga" | "mp3"))
+}
+
+/// FLAC, Ogg Vorbis or MP3 decoded through rodio as it plays.
+pub struct DecodedSource {
+    path: PathBuf,
+    decoder: Decoder<BufReader<File>>,
+    /// Samples taken from the decoder so far.
+    position: u64,
+    channels: usize,
+    sample_rate: u32,
+}
+
+impl DecodedSource {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let decoder = decoder(path)?;
+        let channels = rodio::Source::channels(&decoder) as usize;
+        let sample_rate = rodio::Source::sample_rate(&decoder);
+
+        Ok(DecodedSource {
+            path: path.to_path_buf(),
+            decoder,
+            position: 0,
+            channels,
+            sample_rate,
+        })
+    }
+}
+
+fn decoder(path: &Path) -> Result<Decoder<BufReader<File>>, Error> {
+    let file = File::open(path)?;
+    Ok(Decoder::new(BufReader::new(file))?)
+}
+
+impl Source for DecodedSource {
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn read(&mut self, len: usize) -> Vec<f32> {
+        let samples: Vec<f32> = self
+            .decoder
+            .by_ref()
+            .take(len)
+            .map(|sample| sample as f32 / 32768.0)
+            .collect();
+        self.position += samples.len() as u64;
+        samples
+    }
+
+    // Compressed streams can't jump to a frame, so decode up to it and throw
+    // away everything before it, going back to the start only when seeking backwards.
+    fn seek(&mut self, frame: u64) -> Option<u64> {
+        let target = frame * self.channels as u64;
+        if target < self.position {
+            self.decoder = decoder(&self.path).ok()?;
+            self.position = 0;
+        }
+        let skip = (target - self.position) as usize;
+        self.position += self.decoder.by_ref().take(skip).count() as u64;
+        Some(self.position / self.channels as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::{SampleFormat, WavSpec, WavWriter};
+    use std::env;
+    use std::fs;
+
+    /// A stereo ramp rodio can decode, with the frame number in both channels.
+    fn ramp(name: &str, frames: i16) -> PathBuf {
+        let path = env::temp_dir().join(format!("visualizer-{}-{name}.wav", std::process::id()));
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for frame in 0..frames {
+            writer.write_sample(frame).unwrap();
+            writer.write_sample(frame).unwrap();
+        }
+        writer.finalize().unwrap();
+        path
+    }
+
+    fn frame(source: &mut DecodedSource) -> f32 {
+        source.read(2)[0] * 32768.0
+    }
+
+    #[test]
+    fn seeking_forwards_carries_on_from_the_live_decoder() {
+        let path = ramp("forwards", 1000);
+        let mut source = DecodedSource::open(&path).unwrap();
+        // With the file gone, only the decoder already open can get there.
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(frame(&mut source), 0.0);
+        assert_eq!(source.seek(500), Some(500));
+        assert_eq!(frame(&mut source), 500.0);
+        assert_eq!(source.seek(501), Some(501));
+        assert_eq!(frame(&mut source), 501.0);
+        assert_eq!(source.seek(100), None);
+    }
+
+    #[test]
+    fn seeking_backwards_decodes_again_from_the_start() {
+        let path = ramp("backwards", 1000);
+        let mut source = DecodedSource::open(&path).unwrap();
+
+        assert_eq!(source.seek(800), Some(800));
+        assert_eq!(source.seek(200), Some(200));
+        assert_eq!(frame(&mut source), 200.0);
+        assert_eq!(source.seek(5000), Some(1000));
+        assert!(source.read(2).is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,155 @@
+#[cfg(feature = "portaudio")]
+use portaudio as pa;
+#[cfg(feature = "cpal")]
+use rodio::cpal;
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum Error {
+    Args(String),
+    Format(String),
+    #[cfg(feature = "socool")]
+    Socool(String),
+    Io(io::Error),
+    Wav(hound::Error),
+    Decode(rodio::decoder::DecoderError),
+    Resample(rubato::ResamplerConstructionError),
+    Resampling(rubato::ResampleError),
+    Watch(notify::Error),
+    #[cfg(any(feature = "portaudio", feature = "cpal"))]
+    Device(String),
+    #[cfg(feature = "portaudio")]
+    PortAudio(pa::Error),
+    #[cfg(feature = "cpal")]
+    OutputConfig(cpal::DefaultStreamConfigError),
+    #[cfg(feature = "cpal")]
+    BuildStream(cpal::BuildStreamError),
+    #[cfg(feature = "cpal")]
+    PlayStream(cpal::PlayStreamError),
+    Pixels(pixels::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Args(message) => write!(f, "{message}"),
+            Error::Format(message) => write!(f, "unsupported audio format: {message}"),
+            #[cfg(feature = "socool")]
+            Error::Socool(message) => write!(f, "could not render composition: {message}"),
+            Error::Io(err) => write!(f, "could not open input: {err}"),
+            Error::Wav(err) => write!(f, "could not read wav file: {err}"),
+            Error::Decode(err) => write!(f, "could not decode input: {err}"),
+            Error::Resample(err) => write!(f, "could not create resampler: {err}"),
+            Error::Resampling(err) => write!(f, "resampling failed: {err}"),
+            Error::Watch(err) => write!(f, "could not watch file: {err}"),
+            #[cfg(any(feature = "portaudio", feature = "cpal"))]
+            Error::Device(message) => write!(f, "audio device unavailable: {message}"),
+            #[cfg(feature = "portaudio")]
+            Error::PortAudio(err) => write!(f, "audio output failed: {err}"),
+            #[cfg(feature = "cpal")]
+            Error::OutputConfig(err) => write!(f, "could not configure audio output: {err}"),
+            #[cfg(feature = "cpal")]
+            Error::BuildStream(err) => write!(f, "could not open audio output: {err}"),
+            #[cfg(feature = "cpal")]
+            Error::PlayStream(err) => write!(f, "could not start audio output: {err}"),
+            Error::Pixels(err) => write!(f, "could not create pixel buffer: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Args(_) | Error::Format(_) => None,
+            #[cfg(feature = "socool")]
+            Error::Socool(_) => None,
+            #[cfg(any(feature = "portaudio", feature = "cpal"))]
+            Error::Device(_) => None,
+            Error::Io(err) => Some(err),
+            Error::Wav(err) => Some(err),
+            Error::Decode(err) => Some(err),
+            Error::Resample(err) => Some(err),
+            Error::Resampling(err) => Some(err),
+            Error::Watch(err) => Some(err),
+            #[cfg(feature = "portaudio")]
+            Error::PortAudio(err) => Some(err),
+            #[cfg(feature = "cpal")]
+            Error::OutputConfig(err) => Some(err),
+            #[cfg(feature = "cpal")]
+            Error::BuildStream(err) => Some(err),
+            #[cfg(feature = "cpal")]
+            Error::PlayStream(err) => Some(err),
+            Error::Pixels(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<rodio::decoder::DecoderError> for Error {
+    fn from(err: rodio::decoder::DecoderError) -> Self {
+        Error::Decode(err)
+    }
+}
+
+impl From<hound::Error> for Error {
+    fn from(err: hound::Error) -> Self {
+        Error::Wav(err)
+    }
+}
+
+impl From<rubato::ResamplerConstructionError> for Error {
+    fn from(err: rubato::ResamplerConstructionError) -> Self {
+        Error::Resample(err)
+    }
+}
+
+impl From<rubato::ResampleError> for Error {
+    fn from(err: rubato::ResampleError) -> Self {
+        Error::Resampling(err)
+    }
+}
+
+impl From<notify::Error> for Error {
+    fn from(err: notify::Error) -> Self {
+        Error::Watch(err)
+    }
+}
+
+#[cfg(feature = "portaudio")]
+impl From<pa::Error> for Error {
+    fn from(err: pa::Error) -> Self {
+        Error::PortAudio(err)
+    }
+}
+
+#[cfg(feature = "cpal")]
+impl From<cpal::DefaultStreamConfigError> for Error {
+    fn from(err: cpal::DefaultStreamConfigError) -> Self {
+        Error::OutputConfig(err)
+    }
+}
+
+#[cfg(feature = "cpal")]
+impl From<cpal::BuildStreamError> for Error {
+    fn from(err: cpal::BuildStreamError) -> Self {
+        Error::BuildStream(err)
+    }
+}
+
+#[cfg(feature = "cpal")]
+impl From<cpal::PlayStreamError> for Error {
+    fn from(err: cpal::PlayStreamError) -> Self {
+        Error::PlayStream(err)
+    }
+}
+
+impl From<pixels::Error> for Error {
+    fn from(err: pixels::Error) -> Self {
+        Error::Pixels(err)
+    }
+}
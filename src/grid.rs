@@ -2,6 +2,8 @@ use std::convert::TryInto;
 #[derive(Clone, Copy, Debug, Default)]
 pub struct Cell {
     alive: bool,
+    /// On a band's peak-hold line.
+    peak: bool,
     heat: f32,
     activated_this_turn: bool,
     decay: f32,
@@ -11,6 +13,7 @@ impl Cell {
     fn new(alive: bool, heat: f32) -> Self {
         Self {
             alive,
+            peak: false,
             heat,
             activated_this_turn: false,
             decay: 0.90,
@@ -78,19 +81,40 @@ impl Grid {
         }
     }
 
-    pub fn update_bargraph(&mut self, new_heights_l: &[f32], new_heights_r: &[f32]) {
+    /// Draws one bar per value, and a line across each bar at its peak.
+    pub fn update_bargraph(&mut self, lanes: &[Vec<f32>], peaks: &[Vec<f32>]) {
         for cell in &mut self.cells {
             cell.alive = false;
+            cell.peak = false;
             cell.activated_this_turn = false;
         }
 
-        self.fill_bargraph(&[&new_heights_l[..], &new_heights_r[..]].concat());
+        self.fill_bargraph(&lanes.concat());
+        self.fill_peaks(&peaks.concat());
+    }
+
+    fn fill_peaks(&mut self, peaks: &[f32]) {
+        let bar_width = self.width / peaks.len();
+
+        for (bar_idx, &peak) in peaks.iter().enumerate() {
+            let grid_height = (peak * self.height as f32).round() as usize;
+            // Nothing to hold at the floor.
+            if grid_height == 0 {
+                continue;
+            }
+            let y = self.height - std::cmp::min(grid_height, self.height);
+            for bar_x in bar_idx * bar_width..(bar_idx + 1) * bar_width {
+                self.cells[bar_x + y * self.width].peak = true;
+            }
+        }
     }
 
     pub fn draw(&mut self, screen: &mut [u8]) {
         debug_assert_eq!(screen.len(), 4 * self.cells.len());
         for (c, pix) in self.cells.iter().zip(screen.chunks_exact_mut(4)) {
-            let color = if c.alive {
+            let color = if c.peak {
+                [255u8, 236u8, 244u8, 255u8]
+            } else if c.alive {
                 [144u8, 100u8, 223u8, (c.heat * 255.0) as u8]
             } else {
                 [193u8, 140u8, 183u8, (c.heat * 255.0) as u8]
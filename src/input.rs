@@ -0,0 +1,90 @@
+use crate::cli::RawFormat;
+use crate::decode::{self, DecodedSource};
+use crate::error::Error;
+use crate::raw::RawSource;
+#[cfg(feature = "socool")]
+use crate::socool;
+use crate::source::Source;
+use crate::wav;
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read, Seek};
+use std::path::Path;
+
+pub trait ReadSeek: Read + Seek + Send {}
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+pub type Reader = WavReader<Box<dyn ReadSeek>>;
+
+pub fn is_socool(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "socool")
+}
+
+pub fn is_wav(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("wav"))
+}
+
+/// Whether `path` looks like something `open` can play.
+pub fn is_supported(path: &Path) -> bool {
+    is_wav(path) || is_socool(path) || decode::is_compressed(path)
+}
+
+/// Whether `--watch` can reload `path`: a composition is re-rendered on
+/// save, and a wav reloaded once a render has rewritten it.
+pub fn can_watch(path: &Path) -> bool {
+    is_wav(path) || is_socool(path)
+}
+
+/// Opens a wav, FLAC, Ogg Vorbis or MP3 file, or renders a .socool composition in memory.
+pub fn open(path: &Path) -> Result<Box<dyn Source>, Error> {
+    if decode::is_compressed(path) {
+        return Ok(Box::new(DecodedSource::open(path)?));
+    }
+
+    let source: Box<dyn ReadSeek> = if is_socool(path) {
+        Box::new(Cursor::new(render(path)?))
+    } else {
+        Box::new(BufReader::new(File::open(path)?))
+    };
+
+    let reader: Reader = WavReader::new(source)?;
+    wav::check_spec(&reader.spec())?;
+    Ok(Box::new(reader))
+}
+
+#[cfg(feature = "socool")]
+fn render(path: &Path) -> Result<Vec<u8>, Error> {
+    socool::render(path)
+}
+
+#[cfg(not(feature = "socool"))]
+fn render(path: &Path) -> Result<Vec<u8>, Error> {
+    Err(Error::Args(format!(
+        "{} needs a build with the socool feature",
+        path.display()
+    )))
+}
+
+/// Raw interleaved PCM piped into stdin.
+pub fn stdin(format: RawFormat, channels: usize, sample_rate: u32) -> Box<dyn Source> {
+    let reader = BufReader::new(io::stdin());
+    Box::new(RawSource::new(reader, format, channels, sample_rate))
+}
+
+/// An empty stereo file, used as a placeholder until a composition renders.
+pub fn silence(sample_rate: u32) -> Result<Box<dyn Source>, Error> {
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut bytes = Cursor::new(vec![]);
+    WavWriter::new(&mut bytes, spec)?.finalize()?;
+    bytes.set_position(0);
+
+    let source: Box<dyn ReadSeek> = Box::new(bytes);
+    let reader: Reader = WavReader::new(source)?;
+    Ok(Box::new(reader))
+}
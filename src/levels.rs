@@ -0,0 +1,90 @@
+use crate::cli::{Args, Magnitude};
+
+/// Turns magnitudes, where a full-scale sine reads 1.0, into bar heights
+/// between 0 and 1. The floor and ceiling are in dBFS whatever the mode, so
+/// switching modes keeps the same range and only changes what lies between.
+pub struct Levels {
+    pub mode: Magnitude,
+    floor_db: f32,
+    ceiling_db: f32,
+}
+
+impl Levels {
+    pub fn new(args: &Args) -> Self {
+        Levels {
+            mode: args.magnitude,
+            floor_db: args.floor_db,
+            ceiling_db: args.ceiling_db,
+        }
+    }
+
+    pub fn heights(&self, magnitudes: &[f32]) -> Vec<f32> {
+        let floor = self.measure(db_to_amplitude(self.floor_db));
+        let ceiling = self.measure(db_to_amplitude(self.ceiling_db));
+        magnitudes
+            .iter()
+            .map(|&m| ((self.measure(m) - floor) / (ceiling - floor)).clamp(0.0, 1.0))
+            .collect()
+    }
+
+    /// A line naming the mode and range.
+    pub fn describe(&self) -> String {
+        let mode = match self.mode {
+            Magnitude::Linear => "LINEAR",
+            Magnitude::Db => "DB",
+            Magnitude::Power => "POWER",
+        };
+        format!("LEVELS {mode} {}..{} DBFS", self.floor_db, self.ceiling_db)
+    }
+
+    /// `amplitude` on the scale bars are spaced evenly along.
+    fn measure(&self, amplitude: f32) -> f32 {
+        match self.mode {
+            Magnitude::Linear => amplitude,
+            Magnitude::Power => amplitude * amplitude,
+            Magnitude::Db => 20.0 * amplitude.max(f32::MIN_POSITIVE).log10(),
+        }
+    }
+}
+
+fn db_to_amplitude(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn floor_and_ceiling_bound_the_bars_in_every_mode() {
+        for mode in [Magnitude::Db, Magnitude::Linear, Magnitude::Power] {
+            let levels = Levels {
+                mode,
+                floor_db: -60.0,
+                ceiling_db: -6.0,
+            };
+            let [floor, ceiling] = [-60.0, -6.0].map(db_to_amplitude);
+            let heights = levels.heights(&[0.0, floor * 0.5, floor, ceiling, ceiling * 2.0]);
+            for (height, expected) in heights.iter().zip([0.0, 0.0, 0.0, 1.0, 1.0]) {
+                assert!((height - expected).abs() < 1e-5, "{mode:?}: {heights:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn the_mode_decides_what_lies_halfway() {
+        let halfway = |mode, amplitude| {
+            let levels = Levels {
+                mode,
+                floor_db: -60.0,
+                ceiling_db: 0.0,
+            };
+            levels.heights(&[amplitude])[0]
+        };
+        let floor = db_to_amplitude(-60.0);
+        assert!((halfway(Magnitude::Db, db_to_amplitude(-30.0)) - 0.5).abs() < 1e-5);
+        assert!((halfway(Magnitude::Linear, (floor + 1.0) / 2.0) - 0.5).abs() < 1e-5);
+        let power = ((floor * floor + 1.0) / 2.0).sqrt();
+        assert!((halfway(Magnitude::Power, power) - 0.5).abs() < 1e-5);
+    }
+}
@@ -1,53 +1,86 @@
+mod analysis;
+mod axis;
+mod ballistics;
+mod capture;
+mod cli;
+mod controls;
+#[cfg(feature = "cpal")]
+mod cpal_output;
+mod decode;
+mod error;
 mod grid;
+mod input;
+mod levels;
+mod mix;
+mod output;
+mod overlay;
+#[cfg(feature = "portaudio")]
+mod pa_output;
+mod player;
+mod playlist;
+mod raw;
+mod resample;
+mod ring;
+mod session;
+#[cfg(feature = "socool")]
+mod socool;
+mod source;
+mod stretch;
+mod transport;
+mod watch;
+mod wav;
+mod window;
+use analysis::Spectrum;
+use axis::Axis;
+use ballistics::Ballistics;
+use clap::Parser;
+use cli::{Args, OnEnd, View};
+use controls::Controls;
 use crossbeam_channel as channel;
+use crossbeam_channel::{Receiver, Sender};
+use error::Error;
 use error_iter::ErrorIter as _;
 use grid::*;
-use log::error;
-use pixels::{Error, Pixels, SurfaceTexture};
-use portaudio as pa;
-use std::sync::{Arc, Mutex};
+use levels::Levels;
+use output::Render;
+use overlay::Overlay;
+use pixels::{Pixels, SurfaceTexture};
+use player::{Command, Loaded, Player, Speed};
+use ring::{AudioWriter, Progress};
+use source::Source;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::thread;
-use weresocool_fft::WscFFT;
+use transport::Transport;
 use winit::{
     dpi::LogicalSize,
-    event::{Event, VirtualKeyCode},
+    event::{Event, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
 use winit_input_helper::WinitInputHelper;
 
-const WIDTH: u32 = 2048;
-const HEIGHT: u32 = 1024;
-const LOGICAL_WIDTH: u32 = 1024;
-const LOGICAL_HEIGHT: u32 = 512;
-const BUFFER_SIZE: usize = 1024 * 4;
-const FFT_DIV: usize = 20;
-
 struct FFTHandler {
-    buffer_size: usize,
-    num_results: usize,
-    read_fn: Box<dyn Fn() -> Vec<f32>>,
+    axis: Axis,
+    spectrum: Spectrum,
 }
 
 impl FFTHandler {
-    fn new(buffer_size: usize, num_results: usize, r_fft: channel::Receiver<Vec<f32>>) -> Self {
-        let (read_fn, _) = WscFFT::spawn(buffer_size, r_fft);
+    fn new(args: &Args, spectrum: Spectrum) -> Self {
         FFTHandler {
-            buffer_size,
-            num_results,
-            read_fn: Box::new(read_fn),
+            axis: Axis::new(args),
+            spectrum,
         }
     }
 
+    /// The magnitude of each bar in the latest spectrum.
     fn read_results(&self) -> Vec<f32> {
-        let results = (self.read_fn)();
-        results[2..self.num_results].to_vec()
+        self.axis.map(&self.spectrum.read())
     }
 }
 
 struct WindowHandler {
-    width: u32,
-    height: u32,
     window: winit::window::Window,
 }
 
@@ -57,14 +90,10 @@ impl WindowHandler {
         let window = WindowBuilder::new()
             .with_title("weresoFFT")
             .with_inner_size(size)
-            .build(&event_loop)
+            .build(event_loop)
             .unwrap();
 
-        WindowHandler {
-            width,
-            height,
-            window,
-        }
+        WindowHandler { window }
     }
 
     fn inner_size(&self) -> (u32, u32) {
@@ -76,124 +105,395 @@ impl WindowHandler {
 struct GraphHandler {
     width: usize,
     height: usize,
+    view: View,
     grid: Grid,
+    /// One per lane drawn.
+    ballistics: Vec<Ballistics>,
 }
 
 impl GraphHandler {
-    fn new(width: usize, height: usize) -> Self {
+    fn new(width: usize, height: usize, view: View, ballistics: Vec<Ballistics>) -> Self {
         let grid = Grid::new_bargraph(width, height);
         GraphHandler {
             width,
             height,
+            view,
             grid,
+            ballistics,
         }
     }
 
-    fn update_and_draw(&mut self, pixels: &mut [u8], l: &[f32], r: &[f32]) {
-        self.grid.update_bargraph(l, r);
+    /// Clears the bars, their peaks and their afterglow.
+    fn reset(&mut self) {
+        self.grid = Grid::new_bargraph(self.width, self.height);
+        for ballistics in &mut self.ballistics {
+            ballistics.reset();
+        }
+    }
+
+    /// Smooths each channel's bars, or their mix, and draws them as high as `levels` puts them.
+    fn update_and_draw(&mut self, pixels: &mut [u8], lanes: Vec<Vec<f32>>, levels: &Levels) {
+        let lanes = match self.view {
+            View::Split => lanes,
+            // Mixed before smoothing, so the peaks held are the mix's own.
+            View::Mix => vec![mix(&lanes)],
+        };
+        let (heights, peaks): (Vec<_>, Vec<_>) = self
+            .ballistics
+            .iter_mut()
+            .zip(&lanes)
+            .map(|(ballistics, bars)| {
+                ballistics.update(bars);
+                (
+                    levels.heights(ballistics.levels()),
+                    levels.heights(ballistics.peaks()),
+                )
+            })
+            .unzip();
+        self.grid.update_bargraph(&heights, &peaks);
         self.grid.draw(pixels);
     }
 }
 
-fn get_output_settings(pa: &pa::PortAudio) -> Result<pa::stream::OutputSettings<f32>, Error> {
-    let def_output = pa.default_output_device().unwrap();
-    let output_info = pa.device_info(def_output).unwrap();
-    let latency = output_info.default_low_output_latency;
-    let output_params = pa::StreamParameters::new(def_output, 2, true, latency);
+/// Averages the lanes into one.
+fn mix(lanes: &[Vec<f32>]) -> Vec<f32> {
+    let mut mixed = vec![0.0; lanes[0].len()];
+    for lane in lanes {
+        for (m, v) in mixed.iter_mut().zip(lane) {
+            *m += v / lanes.len() as f32;
+        }
+    }
+    mixed
+}
+
+fn main() {
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("warn,weresocool_visualizer=info"),
+    )
+    .init();
+    let args = Args::parse();
+
+    if let Err(err) = run(args) {
+        log::error!("{err}");
+        for source in err.sources().skip(1) {
+            log::error!("  Caused by: {source}");
+        }
+        std::process::exit(1);
+    }
+}
+
+/// Opens the first track, or stdin when there are none.
+fn open_source(
+    args: &Args,
+    tracks: &[PathBuf],
+    overlay: &Overlay,
+) -> Result<Box<dyn Source>, Error> {
+    let path = match tracks.first() {
+        Some(path) => path,
+        None => return Ok(input::stdin(args.format, args.channels, args.rate)),
+    };
 
-    let output_settings = pa::OutputStreamSettings::new(output_params, 48000.0, BUFFER_SIZE as u32);
+    match input::open(path) {
+        Ok(source) => Ok(source),
+        Err(err) if args.watch => {
+            overlay.show(err.to_string());
+            input::silence(args.sample_rate as u32)
+        }
+        Err(err) => Err(err),
+    }
+}
 
-    Ok(output_settings)
+/// Streams `source`, then the rest of `tracks`, to the audio callback on its own thread.
+fn start_player(
+    args: &Args,
+    source: Box<dyn Source>,
+    tracks: Vec<PathBuf>,
+    audio: AudioWriter,
+    (r_command, s_loaded): (Receiver<Command>, Sender<Loaded>),
+    playhead: Arc<AtomicU64>,
+) -> Result<(), Error> {
+    log::info!(
+        "{} channels at {} Hz",
+        source.channels(),
+        source.sample_rate()
+    );
+
+    let player = Player::new(source, tracks, args, audio, r_command, s_loaded, playhead)?;
+
+    thread::spawn(move || player.run());
+
+    Ok(())
 }
 
-fn main() -> Result<(), Error> {
-    env_logger::init();
+fn run(args: Args) -> Result<(), Error> {
+    args.validate().map_err(Error::Args)?;
+    if args.list_devices {
+        return output::list_devices();
+    }
+    let buffer_size = args.buffer_size;
+
     let event_loop = EventLoop::new();
     let mut input = WinitInputHelper::new();
 
-    let window_handler = WindowHandler::new(LOGICAL_WIDTH, LOGICAL_HEIGHT, &event_loop);
+    let (logical_width, logical_height) = args.logical_size();
+    let window_handler = WindowHandler::new(logical_width, logical_height, &event_loop);
 
     let mut pixels = {
         let (window_width, window_height) = window_handler.inner_size();
         let surface_texture =
             SurfaceTexture::new(window_width, window_height, &window_handler.window);
-        Pixels::new(WIDTH, HEIGHT, surface_texture)?
+        Pixels::new(args.width, args.height, surface_texture)?
     };
 
-    let mut graph_handler = GraphHandler::new(WIDTH as usize, HEIGHT as usize);
-
-    let (s_fft_r, r_fft_r) = channel::unbounded();
-    let (s_fft_l, r_fft_l) = channel::unbounded();
-    let (s_audio, r_audio) = channel::unbounded();
-    let r_audio = Arc::new(Mutex::new(r_audio));
-    let r_audio_clone = Arc::clone(&r_audio);
-
-    let fft_handler_l = FFTHandler::new(BUFFER_SIZE as usize, BUFFER_SIZE / FFT_DIV, r_fft_l);
-    let fft_handler_r = FFTHandler::new(BUFFER_SIZE as usize, BUFFER_SIZE / FFT_DIV, r_fft_r);
-
-    let mut reader = hound::WavReader::open("./src/for_sam.wav").unwrap();
-    let spec = reader.spec();
-    println!("{:?}", spec);
-
-    let pa = pa::PortAudio::new().unwrap();
-    let output_stream_settings = get_output_settings(&pa)?;
-
-    let mut stream = pa
-        .open_non_blocking_stream(
-            output_stream_settings,
-            move |pa::OutputStreamCallbackArgs { buffer, frames, .. }| {
-                let r_audio_lock = r_audio_clone.lock().unwrap();
-                let sender_l = s_fft_l.clone();
-                let sender_r = s_fft_r.clone();
-                let audio_data = (*r_audio_lock)
-                    .recv()
-                    .unwrap_or_else(|_| vec![0.0; frames * 2]); // *2 for stereo
-
-                sender_l
-                    .send(audio_data.iter().step_by(2).cloned().collect())
-                    .unwrap();
-                sender_r
-                    .send(audio_data.iter().skip(1).step_by(2).cloned().collect())
-                    .unwrap();
-
-                for (frame, chunk) in audio_data.chunks_exact(2).enumerate() {
-                    let index = frame * 2;
-                    buffer[index] = chunk[0];
-                    buffer[index + 1] = chunk[1];
-                }
-
-                pa::Continue
-            },
-        )
-        .unwrap();
-
-    let mut interleaved_buffer = vec![0.0; BUFFER_SIZE * 2];
+    let mut tracks = if args.capture || args.stdin {
+        vec![]
+    } else {
+        playlist::expand(&args.inputs)?
+    };
+    window_handler
+        .window
+        .set_title(&playlist::title(&tracks, 0));
 
-    thread::spawn(move || {
-        let mut counter = 0;
+    let overlay = Overlay::default();
+    let progress = Progress::default();
 
-        for sample in reader.samples::<f32>() {
-            let sample = sample.unwrap();
-            let index = counter % (BUFFER_SIZE * 2);
+    let source = if args.capture {
+        None
+    } else {
+        Some(open_source(&args, &tracks, &overlay)?)
+    };
+    let channels = match &source {
+        Some(source) => source.channels(),
+        None => capture::channels()?,
+    };
+    let monitor = !args.capture || args.monitor;
+
+    // Room for four blocks, like the queue of whole blocks this replaced.
+    let (audio_writer, mut audio_reader) =
+        ring::audio(buffer_size * channels * 4, progress.clone());
+
+    let window = window::Selection::new(args.window);
+    let flushes = audio_writer.flushes();
+    let (mut feed, spectra) =
+        analysis::spawn(&args, channels, window.clone(), Arc::clone(&flushes));
+    let fft_handlers: Vec<_> = spectra
+        .into_iter()
+        .map(|spectrum| FFTHandler::new(&args, spectrum))
+        .collect();
+    let mut controls = Controls::new(window, Levels::new(&args));
+
+    let axis = &fft_handlers[0].axis;
+    let lanes = match args.view {
+        View::Split => channels,
+        View::Mix => 1,
+    };
+    if axis.bars() == 0 || axis.bars() * lanes > args.width as usize {
+        return Err(Error::Args(format!(
+            "{} bars per channel don't fit across a width of {}",
+            axis.bars(),
+            args.width
+        )));
+    }
+    let ballistics = (0..lanes).map(|_| Ballistics::new(&args)).collect();
+    let mut graph_handler = GraphHandler::new(
+        args.width as usize,
+        args.height as usize,
+        args.view,
+        ballistics,
+    );
+    let (low_hz, high_hz) = axis.range();
+    log::info!(
+        "showing {:.0} Hz to {:.0} Hz in {} bars",
+        low_hz,
+        high_hz,
+        axis.bars()
+    );
+
+    let (s_command, r_command) = channel::unbounded();
+    let mut transport = Transport::new(
+        s_command.clone(),
+        Arc::clone(&progress.playhead),
+        args.sample_rate,
+        (low_hz, high_hz),
+        args.seek_step,
+        args.long_seek_step,
+        Speed {
+            rate: args.speed,
+            preserve_pitch: args.preserve_pitch,
+        },
+    );
+    // Neither capture nor stdin can loop, so they have no loop points to restore.
+    if let (false, false, Some(path)) = (
+        args.capture,
+        args.stdin,
+        args.session_path(tracks.first().map(PathBuf::as_path)),
+    ) {
+        transport.load_session(path);
+    }
+    if args.stdin {
+        transport.without_seeking();
+    }
+    if let Some((low, high)) = transport.source_range() {
+        log::info!(
+            "at {:.2}x that is {:.0} Hz to {:.0} Hz in the source",
+            args.speed,
+            low,
+            high
+        );
+    }
+    let paused = transport.paused();
+    let loader = s_command.clone();
+    let (s_loaded, r_loaded) = channel::unbounded();
+    // Replaced when files are dropped on the window.
+    let mut _watching = match (args.watch, tracks.first()) {
+        (true, Some(path)) => Some(watch::spawn(
+            path.clone(),
+            args.reload_from_start,
+            s_command,
+            overlay.clone(),
+        )?),
+        _ => None,
+    };
 
-            interleaved_buffer[index] = sample;
+    let _capture_stream = match source {
+        Some(source) => {
+            start_player(
+                &args,
+                source,
+                tracks.clone(),
+                audio_writer,
+                (r_command, s_loaded),
+                Arc::clone(&progress.playhead),
+            )?;
+            None
+        }
+        None => {
+            // Nothing plays commands while capturing, so don't let them queue up unread.
+            drop(r_command);
+            let stream = capture::start(args.sample_rate, buffer_size, channels, audio_writer)?;
+            Some(stream)
+        }
+    };
 
-            counter += 1;
+    let mut scratch = vec![0.0; buffer_size * channels];
+    let render: Render = Box::new(move |buffer: &mut [f32]| {
+        if paused.load(Ordering::Relaxed) {
+            buffer.fill(0.0);
+            return false;
+        }
 
-            if counter % (BUFFER_SIZE * 2) == 0 {
-                s_audio.send(interleaved_buffer.clone()).unwrap();
+        let mut played = false;
+        for out in buffer.chunks_mut(buffer_size * 2) {
+            let samples = &mut scratch[..out.len() / 2 * channels];
+            let read = audio_reader.read(samples);
+            played |= read > 0;
+            samples[read..].fill(0.0);
+            feed.push(samples);
+
+            if monitor {
+                mix::to_stereo(samples, channels, out);
+            } else {
+                out.fill(0.0);
             }
         }
+        played
     });
 
-    stream.start().unwrap();
+    let mut output = output::open(&args)?;
+    output.start(render)?;
 
+    let mut was_ended = false;
+    let mut playlist = 0;
+    let mut track = Some(0);
+    let mut dropped = vec![];
+    let mut flushed = 0;
     event_loop.run(move |event, _, control_flow| {
-        if let Event::RedrawRequested(_) = event {
-            let fft_results_l = fft_handler_l.read_results();
-            let fft_results_r = fft_handler_r.read_results();
+        // The analysis starts afresh after a seek, and so do the bars.
+        let flushes = flushes.load(Ordering::Acquire);
+        if flushes != flushed {
+            flushed = flushes;
+            graph_handler.reset();
+        }
+
+        for loaded in r_loaded.try_iter() {
+            match loaded {
+                Ok((new_playlist, new_tracks)) => {
+                    overlay.clear();
+                    tracks = new_tracks;
+                    playlist = new_playlist;
+                    track = None;
+                    // Follow the dropped file, so saving the old one doesn't swap it back in.
+                    _watching = None;
+                    if args.watch && matches!(tracks.as_slice(), [path] if input::can_watch(path)) {
+                        match watch::spawn(
+                            tracks[0].clone(),
+                            args.reload_from_start,
+                            loader.clone(),
+                            overlay.clone(),
+                        ) {
+                            Ok(watch) => _watching = Some(watch),
+                            Err(err) => overlay.show(err.to_string()),
+                        }
+                    }
+                }
+                Err(err) => overlay.show(err.to_string()),
+            }
+        }
 
-            graph_handler.update_and_draw(pixels.frame_mut(), &fft_results_l, &fft_results_r);
+        // Audio from before the last drop says nothing about the tracks now.
+        if progress.playlist.load(Ordering::Acquire) == playlist {
+            let heard = progress.track.load(Ordering::Relaxed);
+            if track != Some(heard) {
+                track = Some(heard);
+                window_handler
+                    .window
+                    .set_title(&playlist::title(&tracks, heard));
+                // The player drops the loop points on a new track, so send its own.
+                if let Some(path) = args.session_path(tracks.get(heard).map(PathBuf::as_path)) {
+                    transport.load_session(path);
+                }
+            }
+        }
+
+        let ended = progress.ended.load(Ordering::Relaxed);
+        if ended && !was_ended {
+            match args.on_end {
+                // Looping is done by the player, so it never ends.
+                OnEnd::Stop | OnEnd::Loop => {}
+                OnEnd::Exit => {
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+                // The player moves on by itself, so this is the end of the last track.
+                OnEnd::Next => {}
+            }
+        }
+        was_ended = ended;
+
+        if let Event::RedrawRequested(_) = event {
+            let fft_results = fft_handlers.iter().map(FFTHandler::read_results).collect();
+            graph_handler.update_and_draw(pixels.frame_mut(), fft_results, controls.levels());
+            overlay.draw(
+                pixels.frame_mut(),
+                args.width as usize,
+                args.height as usize,
+            );
+            let underruns = progress.underruns.load(Ordering::Relaxed);
+            let status = [
+                transport.status(),
+                controls.status(),
+                (underruns > 0).then(|| format!("UNDERRUNS {underruns}")),
+                ended.then(|| "END".to_string()),
+            ];
+            let status: Vec<_> = status.into_iter().flatten().collect();
+            if !status.is_empty() {
+                overlay::draw_status(
+                    pixels.frame_mut(),
+                    args.width as usize,
+                    args.height as usize,
+                    &status.join("   "),
+                );
+            }
 
             if pixels.render().is_err() {
                 *control_flow = ControlFlow::Exit;
@@ -202,17 +502,87 @@ fn main() -> Result<(), Error> {
         }
 
         if input.update(&event) {
-            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+            if input.key_pressed(VirtualKeyCode::Escape)
+                || input.close_requested()
+                || input.destroyed()
+            {
                 *control_flow = ControlFlow::Exit;
                 return;
             }
+            transport.handle_input(&input);
+            controls.handle_input(&input);
             if let Some(size) = input.window_resized() {
                 _ = pixels.resize_surface(size.width, size.height);
             }
         }
 
+        if let Event::WindowEvent {
+            event: WindowEvent::DroppedFile(path),
+            ..
+        } = &event
+        {
+            dropped.push(path.clone());
+        }
+
         if let Event::MainEventsCleared = event {
+            // Every file in one drop arrives before the events are cleared, so they load together.
+            if args.capture && !dropped.is_empty() {
+                dropped.clear();
+                overlay.show("can't load files while capturing");
+            }
+            if !dropped.is_empty() {
+                // Opening can take a while, so the player does it and answers once it has.
+                _ = loader.send(Command::Load(std::mem::take(&mut dropped)));
+            }
             window_handler.window.request_redraw();
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::{SampleFormat, WavSpec, WavWriter};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn every_channel_of_the_first_track_gets_a_spectrum() {
+        let path = std::env::temp_dir().join(format!("visualizer-{}-quad.wav", std::process::id()));
+        let spec = WavSpec {
+            channels: 4,
+            sample_rate: 48000,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        // Only the last channel carries anything.
+        for n in 0..256 {
+            for sample in [0.0, 0.0, 0.0, (n as f32 * 0.3).sin()] {
+                writer.write_sample(sample).unwrap();
+            }
+        }
+        writer.finalize().unwrap();
+
+        let input = path.to_str().unwrap();
+        let args =
+            Args::try_parse_from(["visualizer", input, "--fft-size=256", "--hop=64"]).unwrap();
+        let tracks = [path.clone()];
+        let mut source = open_source(&args, &tracks, &Overlay::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(source.channels(), 4);
+
+        let window = window::Selection::new(args.window);
+        let flushes = Arc::new(AtomicU64::new(0));
+        let (mut feed, spectra) = analysis::spawn(&args, source.channels(), window, flushes);
+        assert_eq!(spectra.len(), 4);
+
+        feed.push(&source.read(256 * 4));
+        let loudest = |spectrum: &Spectrum| spectrum.read().into_iter().fold(0.0, f32::max);
+        let start = Instant::now();
+        while loudest(&spectra[3]) < 0.5 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(loudest(&spectra[0]), 0.0);
+    }
+}
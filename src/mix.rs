@@ -0,0 +1,98 @@
+/// Splits an interleaved buffer into one buffer per channel.
+pub fn deinterleave(interleaved: &[f32], channels: usize) -> Vec<Vec<f32>> {
+    (0..channels)
+        .map(|channel| {
+            interleaved
+                .iter()
+                .skip(channel)
+                .step_by(channels)
+                .cloned()
+                .collect()
+        })
+        .collect()
+}
+
+/// Folds interleaved frames of any channel count into interleaved stereo.
+/// Mono is duplicated to both sides; otherwise even channels are averaged into
+/// the left output and odd channels into the right.
+pub fn to_stereo(interleaved: &[f32], channels: usize, out: &mut [f32]) {
+    let left_count = channels.div_ceil(2);
+    let right_count = channels / 2;
+
+    for (frame, out_frame) in interleaved
+        .chunks_exact(channels)
+        .zip(out.chunks_exact_mut(2))
+    {
+        let left: f32 = frame.iter().step_by(2).sum::<f32>() / left_count as f32;
+        let right = if right_count == 0 {
+            left
+        } else {
+            frame.iter().skip(1).step_by(2).sum::<f32>() / right_count as f32
+        };
+
+        out_frame[0] = left;
+        out_frame[1] = right;
+    }
+}
+
+/// Interleaves one buffer per channel into `out`.
+pub fn interleave(channels: &[Vec<f32>], out: &mut Vec<f32>) {
+    let frames = channels.iter().map(Vec::len).min().unwrap_or(0);
+    for frame in 0..frames {
+        out.extend(channels.iter().map(|channel| channel[frame]));
+    }
+}
+
+/// Converts interleaved frames from one channel count to another. Mono is
+/// copied to every channel; fewer channels are repeated in turn; more are
+/// folded down by averaging every `to`th channel together, as `to_stereo` does.
+pub fn remix(interleaved: &[f32], from: usize, to: usize) -> Vec<f32> {
+    let mut out = Vec::with_capacity(interleaved.len() / from * to);
+    for frame in interleaved.chunks_exact(from) {
+        for channel in 0..to {
+            if from <= to {
+                out.push(frame[channel % from]);
+            } else {
+                let folded = frame.iter().skip(channel).step_by(to);
+                let count = (from - channel).div_ceil(to);
+                out.push(folded.sum::<f32>() / count as f32);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deinterleaving_and_interleaving_round_trip() {
+        let interleaved = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let channels = deinterleave(&interleaved, 3);
+        assert_eq!(channels, [vec![1.0, 4.0], vec![2.0, 5.0], vec![3.0, 6.0]]);
+
+        let mut out = vec![];
+        interleave(&channels, &mut out);
+        assert_eq!(out, interleaved);
+    }
+
+    #[test]
+    fn mono_goes_to_both_sides_and_more_channels_are_averaged() {
+        let mut out = [0.0; 4];
+        to_stereo(&[0.5, -0.5], 1, &mut out);
+        assert_eq!(out, [0.5, 0.5, -0.5, -0.5]);
+
+        let mut out = [0.0; 2];
+        to_stereo(&[1.0, 0.5, 0.0, 0.0, 0.5], 5, &mut out);
+        assert_eq!(out, [0.5, 0.25]);
+    }
+
+    #[test]
+    fn remixing_copies_up_and_folds_down() {
+        assert_eq!(remix(&[0.5, -0.5], 1, 2), [0.5, 0.5, -0.5, -0.5]);
+        assert_eq!(remix(&[1.0, 2.0], 2, 3), [1.0, 2.0, 1.0]);
+        assert_eq!(remix(&[1.0, 0.5, 0.0, 0.0, 0.5], 5, 2), [0.5, 0.25]);
+        assert_eq!(remix(&[1.0, 0.5], 2, 1), [0.75]);
+    }
+}
@@ -0,0 +1,105 @@
+use crate::cli::{Args, Backend};
+#[cfg(feature = "cpal")]
+use crate::cpal_output::CpalOutput;
+use crate::error::Error;
+#[cfg(feature = "portaudio")]
+use crate::pa_output::PortAudioOutput;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Fills a buffer of interleaved stereo frames, however many the device asks
+/// for, and returns false if there was nothing to play, such as while paused.
+/// Called on the audio thread, so it must not block or allocate.
+pub type Render = Box<dyn FnMut(&mut [f32]) -> bool + Send>;
+
+/// Somewhere to play interleaved stereo at a fixed sample rate, such as a sound card.
+pub trait AudioOutput {
+    /// Starts calling `render` for audio, until the output is dropped.
+    fn start(&mut self, render: Render) -> Result<(), Error>;
+}
+
+/// Opens the output picked by `--backend` and the options that go with it.
+pub fn open(args: &Args) -> Result<Box<dyn AudioOutput>, Error> {
+    let (sample_rate, buffer_size) = (args.sample_rate, args.buffer_size);
+    match args.backend {
+        Backend::Null => Ok(Box::new(NullOutput::new(
+            sample_rate,
+            buffer_size,
+            args.offline,
+        ))),
+        #[cfg(feature = "portaudio")]
+        Backend::Portaudio => Ok(Box::new(PortAudioOutput::new(
+            args.device.as_deref(),
+            args.latency,
+            sample_rate,
+            buffer_size,
+        )?)),
+        #[cfg(feature = "cpal")]
+        Backend::Cpal => Ok(Box::new(CpalOutput::new(sample_rate, buffer_size)?)),
+    }
+}
+
+#[cfg(feature = "portaudio")]
+pub use crate::pa_output::list_devices;
+
+#[cfg(not(feature = "portaudio"))]
+pub fn list_devices() -> Result<(), Error> {
+    Err(Error::Args(
+        "--list-devices needs a build with the portaudio feature".to_string(),
+    ))
+}
+
+/// Throws the audio away, for machines without a sound device. Blocks are
+/// pulled on a software clock at the sample rate, or back to back when offline
+/// as long as there is something to play.
+pub struct NullOutput {
+    sample_rate: f64,
+    buffer_size: usize,
+    offline: bool,
+    stop: Arc<AtomicBool>,
+}
+
+impl NullOutput {
+    pub fn new(sample_rate: f64, buffer_size: usize, offline: bool) -> Self {
+        NullOutput {
+            sample_rate,
+            buffer_size,
+            offline,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl AudioOutput for NullOutput {
+    fn start(&mut self, mut render: Render) -> Result<(), Error> {
+        let period = Duration::from_secs_f64(self.buffer_size as f64 / self.sample_rate);
+        let mut buffer = vec![0.0; self.buffer_size * 2];
+        let offline = self.offline;
+        let stop = Arc::clone(&self.stop);
+
+        thread::spawn(move || {
+            // Sleep until each block is due rather than for a fixed period, so
+            // time spent rendering doesn't make the clock drift.
+            let mut due = Instant::now();
+            while !stop.load(Ordering::Relaxed) {
+                let played = render(&mut buffer);
+                if offline && played {
+                    due = Instant::now();
+                } else {
+                    due += period;
+                    thread::sleep(due.saturating_duration_since(Instant::now()));
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+impl Drop for NullOutput {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,114 @@
+use font8x8::{UnicodeFonts, BASIC_FONTS};
+use std::sync::{Arc, Mutex};
+
+const GLYPH_SIZE: usize = 8;
+const SCALE: usize = 3;
+const MARGIN: usize = GLYPH_SIZE * SCALE;
+const BACKGROUND: [u8; 4] = [40, 0, 16, 235];
+const FOREGROUND: [u8; 4] = [255, 236, 244, 255];
+
+/// A message shown over the graph until it is cleared, shared with background threads.
+#[derive(Clone, Default)]
+pub struct Overlay {
+    message: Arc<Mutex<Option<String>>>,
+}
+
+impl Overlay {
+    pub fn show(&self, message: impl Into<String>) {
+        *self.message.lock().unwrap() = Some(message.into());
+    }
+
+    pub fn clear(&self) {
+        *self.message.lock().unwrap() = None;
+    }
+
+    pub fn draw(&self, screen: &mut [u8], width: usize, height: usize) {
+        if let Some(message) = self.message.lock().unwrap().as_deref() {
+            draw_text_box(screen, width, height, message);
+        }
+    }
+}
+
+/// Draws a single line of text along the bottom of the screen.
+pub fn draw_status(screen: &mut [u8], width: usize, height: usize, status: &str) {
+    let cell = GLYPH_SIZE * SCALE;
+    let strip_height = cell + MARGIN;
+    if height < strip_height {
+        return;
+    }
+    let top = height - strip_height;
+
+    for pix in screen[top * width * 4..height * width * 4].chunks_exact_mut(4) {
+        pix.copy_from_slice(&BACKGROUND);
+    }
+
+    let columns = width.saturating_sub(2 * MARGIN) / cell;
+    for (column, c) in status.chars().take(columns).enumerate() {
+        let glyph = BASIC_FONTS.get(c).unwrap_or([0; GLYPH_SIZE]);
+        draw_glyph(
+            screen,
+            width,
+            MARGIN + column * cell,
+            top + MARGIN / 2,
+            &glyph,
+        );
+    }
+}
+
+fn wrap(message: &str, columns: usize) -> Vec<String> {
+    let mut lines = vec![];
+    for line in message.lines() {
+        let chars: Vec<char> = line.chars().collect();
+        if chars.is_empty() {
+            lines.push(String::new());
+        }
+        for chunk in chars.chunks(columns) {
+            lines.push(chunk.iter().collect());
+        }
+    }
+    lines
+}
+
+fn draw_text_box(screen: &mut [u8], width: usize, height: usize, message: &str) {
+    let cell = GLYPH_SIZE * SCALE;
+    let columns = width.saturating_sub(2 * MARGIN) / cell;
+    let max_rows = height.saturating_sub(2 * MARGIN) / cell;
+    if columns == 0 || max_rows == 0 {
+        return;
+    }
+
+    let lines = wrap(message, columns);
+    let rows = lines.len().min(max_rows);
+    let box_height = (rows * cell + 2 * MARGIN).min(height);
+
+    for pix in screen[..box_height * width * 4].chunks_exact_mut(4) {
+        pix.copy_from_slice(&BACKGROUND);
+    }
+
+    for (row, line) in lines.iter().take(rows).enumerate() {
+        for (column, c) in line.chars().enumerate() {
+            let glyph = BASIC_FONTS.get(c).unwrap_or([0; GLYPH_SIZE]);
+            let x0 = MARGIN + column * cell;
+            let y0 = MARGIN + row * cell;
+            draw_glyph(screen, width, x0, y0, &glyph);
+        }
+    }
+}
+
+fn draw_glyph(screen: &mut [u8], width: usize, x0: usize, y0: usize, glyph: &[u8; GLYPH_SIZE]) {
+    for (gy, bits) in glyph.iter().enumerate() {
+        for gx in 0..GLYPH_SIZE {
+            if bits & (1 << gx) == 0 {
+                continue;
+            }
+            for sy in 0..SCALE {
+                for sx in 0..SCALE {
+                    let x = x0 + gx * SCALE + sx;
+                    let y = y0 + gy * SCALE + sy;
+                    let idx = (x + y * width) * 4;
+                    screen[idx..idx + 4].copy_from_slice(&FOREGROUND);
+                }
+            }
+        }
+    }
+}
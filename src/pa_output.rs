@@ -0,0 +1,196 @@
+use crate::cli::Latency;
+use crate::error::Error;
+use crate::output::{AudioOutput, Render};
+use portaudio as pa;
+
+const COMMON_RATES: [f64; 6] = [44100.0, 48000.0, 88200.0, 96000.0, 176400.0, 192000.0];
+
+/// Plays through a PortAudio output device, the default one unless another is picked.
+pub struct PortAudioOutput {
+    pa: pa::PortAudio,
+    device: pa::DeviceIndex,
+    latency: Latency,
+    sample_rate: f64,
+    buffer_size: usize,
+    stream: Option<pa::Stream<pa::NonBlocking, pa::Output<f32>>>,
+}
+
+impl PortAudioOutput {
+    /// `device` is an index from `--list-devices`, or part of a device's name.
+    pub fn new(
+        device: Option<&str>,
+        latency: Latency,
+        sample_rate: f64,
+        buffer_size: usize,
+    ) -> Result<Self, Error> {
+        let pa = pa::PortAudio::new()?;
+        let device = match device {
+            Some(device) => find_device(&pa, device)?,
+            None => pa.default_output_device()?,
+        };
+
+        Ok(PortAudioOutput {
+            pa,
+            device,
+            latency,
+            sample_rate,
+            buffer_size,
+            stream: None,
+        })
+    }
+}
+
+fn find_device(pa: &pa::PortAudio, wanted: &str) -> Result<pa::DeviceIndex, Error> {
+    let mut outputs = vec![];
+    for device in pa.devices()? {
+        let (index, info) = device?;
+        if info.max_output_channels >= 2 {
+            outputs.push((index, info.name.to_string()));
+        }
+    }
+
+    if let Ok(wanted) = wanted.parse::<u32>() {
+        return outputs
+            .iter()
+            .find(|(index, _)| index.0 == wanted)
+            .map(|(index, _)| *index)
+            .ok_or_else(|| Error::Device(format!("no stereo output device with index {wanted}")));
+    }
+
+    // An exact name wins; otherwise the name has to narrow it down to one device.
+    if let Some((index, _)) = outputs
+        .iter()
+        .find(|(_, name)| name.eq_ignore_ascii_case(wanted))
+    {
+        return Ok(*index);
+    }
+    let wanted_lower = wanted.to_lowercase();
+    let matches: Vec<_> = outputs
+        .iter()
+        .filter(|(_, name)| name.to_lowercase().contains(&wanted_lower))
+        .collect();
+    match matches.as_slice() {
+        [(index, _)] => Ok(*index),
+        [] => Err(Error::Device(format!(
+            "no stereo output device matches \"{wanted}\"; see --list-devices"
+        ))),
+        _ => {
+            let names: Vec<_> = matches
+                .iter()
+                .map(|(index, name)| format!("{}: {name}", index.0))
+                .collect();
+            Err(Error::Device(format!(
+                "\"{wanted}\" matches more than one device: {}",
+                names.join(", ")
+            )))
+        }
+    }
+}
+
+fn get_output_settings(
+    pa: &pa::PortAudio,
+    device: pa::DeviceIndex,
+    latency: Latency,
+    sample_rate: f64,
+    buffer_size: usize,
+) -> Result<pa::stream::OutputSettings<f32>, Error> {
+    let output_info = pa.device_info(device)?;
+    let latency = match latency {
+        Latency::Low => output_info.default_low_output_latency,
+        Latency::High => output_info.default_high_output_latency,
+        Latency::Seconds(seconds) => seconds,
+    };
+    let output_params = pa::StreamParameters::new(device, 2, true, latency);
+
+    let output_settings =
+        pa::OutputStreamSettings::new(output_params, sample_rate, buffer_size as u32);
+
+    Ok(output_settings)
+}
+
+impl AudioOutput for PortAudioOutput {
+    fn start(&mut self, mut render: Render) -> Result<(), Error> {
+        let output_stream_settings = get_output_settings(
+            &self.pa,
+            self.device,
+            self.latency,
+            self.sample_rate,
+            self.buffer_size,
+        )?;
+
+        let mut stream = self.pa.open_non_blocking_stream(
+            output_stream_settings,
+            move |pa::OutputStreamCallbackArgs { buffer, .. }| {
+                render(buffer);
+                pa::Continue
+            },
+        )?;
+        stream.start()?;
+
+        self.stream = Some(stream);
+        Ok(())
+    }
+}
+
+/// Prints every host API and its devices, with what each can play.
+pub fn list_devices() -> Result<(), Error> {
+    let pa = pa::PortAudio::new()?;
+    let default_host = pa.default_host_api()?;
+    let default_output = pa.default_output_device().ok();
+
+    let mut devices = vec![];
+    for device in pa.devices()? {
+        devices.push(device?);
+    }
+
+    for (host, host_info) in pa.host_apis() {
+        let marker = if host == default_host {
+            " (default)"
+        } else {
+            ""
+        };
+        println!("{}{marker}", host_info.name);
+
+        for (index, info) in devices.iter().filter(|(_, info)| info.host_api == host) {
+            let marker = if Some(*index) == default_output {
+                " (default output)"
+            } else {
+                ""
+            };
+            println!("  {}: {}{marker}", index.0, info.name);
+            println!(
+                "      {} in, {} out, default {} Hz",
+                info.max_input_channels, info.max_output_channels, info.default_sample_rate
+            );
+            if info.max_output_channels < 2 {
+                continue;
+            }
+
+            let rates: Vec<_> = COMMON_RATES
+                .iter()
+                .filter(|&&rate| {
+                    let params = pa::StreamParameters::<f32>::new(
+                        *index,
+                        2,
+                        true,
+                        info.default_low_output_latency,
+                    );
+                    pa.is_output_format_supported(params, rate).is_ok()
+                })
+                .map(|rate| rate.to_string())
+                .collect();
+            if rates.is_empty() {
+                println!("      no common rate supported for stereo output");
+            } else {
+                println!("      stereo output at {} Hz", rates.join(", "));
+            }
+            println!(
+                "      output latency {:.1} ms low, {:.1} ms high",
+                info.default_low_output_latency * 1000.0,
+                info.default_high_output_latency * 1000.0
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,725 @@
+use crate::cli::{Args, OnEnd};
+use crate::error::Error;
+use crate::input;
+use crate::playlist;
+use crate::resample::Resampler;
+use crate::ring::{AudioWriter, Stamp};
+use crate::source::{self, Source};
+use crate::stretch::Stretcher;
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+pub enum Command {
+    /// Replace the current source, continuing from the current playhead when
+    /// it fits unless `from_start` is set.
+    Swap {
+        source: Box<dyn Source>,
+        from_start: bool,
+    },
+    /// Move the playhead by this many seconds.
+    SeekBy(f64),
+    /// Move the playhead to this many seconds from the start.
+    SeekTo(f64),
+    /// Play the region between two times in seconds over and over, or stop looping.
+    Loop(Option<(f64, f64)>),
+    /// Change how fast the source plays.
+    Speed(Speed),
+    /// Skip to the start of the next track.
+    Next,
+    /// Go back to the start of the previous track, or of the first one.
+    Previous,
+    /// Replace the tracks with these files and directories, starting straight away on the first.
+    Load(Vec<PathBuf>),
+}
+
+/// What the player answers a `Load` with: the new tracks and the playlist
+/// their blocks are stamped with, or why they couldn't be loaded.
+pub type Loaded = Result<(usize, Vec<PathBuf>), Error>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Speed {
+    /// 1.0 plays at normal speed, 0.5 at half speed.
+    pub rate: f64,
+    /// Stretch time without changing pitch, rather than playing faster or slower like tape.
+    pub preserve_pitch: bool,
+}
+
+impl Default for Speed {
+    fn default() -> Self {
+        Speed {
+            rate: 1.0,
+            preserve_pitch: false,
+        }
+    }
+}
+
+/// Turns source audio into what is heard: resampled to the output rate, then
+/// sped up or slowed down. Varispeed folds the speed into the resampling ratio,
+/// so pitch follows; otherwise a stretcher changes the tempo alone.
+struct Stages {
+    resampler: Option<Resampler>,
+    stretcher: Option<Stretcher>,
+}
+
+impl Stages {
+    fn new(
+        source_rate: f64,
+        output_rate: f64,
+        channels: usize,
+        buffer_size: usize,
+        speed: Speed,
+    ) -> Result<Self, Error> {
+        if speed.preserve_pitch && speed.rate != 1.0 {
+            Ok(Stages {
+                resampler: Resampler::for_rates(source_rate, output_rate, channels, buffer_size)?,
+                stretcher: Some(Stretcher::new(speed.rate, channels, output_rate)),
+            })
+        } else {
+            Ok(Stages {
+                resampler: Resampler::for_rates(
+                    source_rate * speed.rate,
+                    output_rate,
+                    channels,
+                    buffer_size,
+                )?,
+                stretcher: None,
+            })
+        }
+    }
+
+    fn process(&mut self, chunk: Vec<f32>, done: bool) -> Result<Vec<f32>, Error> {
+        let mut audio = match self.resampler.as_mut() {
+            Some(resampler) => {
+                let mut resampled = resampler.process(&chunk)?;
+                if done {
+                    resampled.extend(resampler.flush()?);
+                }
+                resampled
+            }
+            None => chunk,
+        };
+        if let Some(stretcher) = self.stretcher.as_mut() {
+            let mut stretched = stretcher.process(&audio);
+            if done {
+                stretched.extend(stretcher.flush());
+            }
+            audio = stretched;
+        }
+        Ok(audio)
+    }
+
+    fn reset(&mut self) {
+        if let Some(resampler) = self.resampler.as_mut() {
+            resampler.reset();
+        }
+        if let Some(stretcher) = self.stretcher.as_mut() {
+            stretcher.reset();
+        }
+    }
+}
+
+/// A track opened and ready to play from its start.
+struct Track {
+    index: usize,
+    source: Box<dyn Source>,
+    stages: Stages,
+}
+
+/// Streams blocks of `buffer_size` interleaved frames at the output rate to the audio callback.
+/// Positions are source frames at the output rate, which only match output
+/// frames at normal speed.
+///
+/// Every track is remixed to the first one's channel count and resampled
+/// from its own rate, so the output and the FFT lanes stay the same throughout.
+pub struct Player {
+    source: Box<dyn Source>,
+    tracks: Vec<PathBuf>,
+    /// Index of the track being read.
+    track: usize,
+    /// Counts the times the tracks have been replaced.
+    playlist: usize,
+    /// Carry on into the next track at the end of each one.
+    advance_tracks: bool,
+    stages: Stages,
+    speed: Speed,
+    buffer_size: usize,
+    channels: usize,
+    output_rate: f64,
+    pending: Vec<f32>,
+    /// Position of the first pending sample.
+    position: f64,
+    /// Source frames read so far, at the source's own rate.
+    read: u64,
+    /// Output frames sent since the last jump.
+    sent: u64,
+    /// Output frames that everything read since the last jump turns into, counted like `sent`.
+    read_to: f64,
+    /// Where, counted like `sent`, playback jumps back to the start of the loop, and where to.
+    wraps: VecDeque<(u64, f64)>,
+    loop_region: Option<(f64, f64)>,
+    /// Start again from the beginning at the end of the source.
+    repeat: bool,
+    /// Counts jumps, so a block waiting for room in the queue can tell it is stale.
+    jumps: u64,
+    /// Blocks for the audio callback. The spectrum is taken from what the
+    /// callback plays, so it follows without a queue of its own.
+    audio: AudioWriter,
+    commands: Receiver<Command>,
+    loaded: Sender<Loaded>,
+    playhead: Arc<AtomicU64>,
+}
+
+impl Player {
+    /// `source` is the first of `tracks`, already opened, or the only input
+    /// when `tracks` is empty.
+    pub fn new(
+        source: Box<dyn Source>,
+        tracks: Vec<PathBuf>,
+        args: &Args,
+        audio: AudioWriter,
+        commands: Receiver<Command>,
+        loaded: Sender<Loaded>,
+        playhead: Arc<AtomicU64>,
+    ) -> Result<Self, Error> {
+        let (buffer_size, output_rate) = (args.buffer_size, args.sample_rate);
+        let channels = source.channels();
+        let speed = Speed::default();
+        let stages = Stages::new(
+            source.sample_rate() as f64,
+            output_rate,
+            channels,
+            buffer_size,
+            speed,
+        )?;
+
+        Ok(Player {
+            source,
+            tracks,
+            track: 0,
+            playlist: 0,
+            advance_tracks: args.on_end == OnEnd::Next,
+            stages,
+            speed,
+            buffer_size,
+            channels,
+            output_rate,
+            pending: vec![],
+            position: 0.0,
+            read: 0,
+            sent: 0,
+            read_to: 0.0,
+            wraps: VecDeque::new(),
+            loop_region: None,
+            repeat: args.on_end == OnEnd::Loop,
+            jumps: 0,
+            audio,
+            commands,
+            loaded,
+            playhead,
+        })
+    }
+
+    pub fn run(mut self) {
+        let block_len = self.buffer_size * self.channels;
+
+        loop {
+            if let Ok(command) = self.commands.try_recv() {
+                self.handle(command);
+            }
+            let jumps = self.jumps;
+
+            let len = self.read_len(block_len);
+            let chunk = self.source.read(len);
+            let mut done = chunk.len() < len;
+            self.count_read(chunk.len() / self.channels);
+
+            if self.wrap_loop(done) {
+                done = false;
+            }
+
+            match self.stages.process(chunk, done) {
+                Ok(audio) => self.pending.extend(audio),
+                Err(err) => {
+                    // Stop reading this source and play out what was made of it.
+                    log::error!("{err}");
+                    done = true;
+                }
+            }
+
+            while self.pending.len() >= block_len {
+                let block: Vec<f32> = self.pending.drain(..block_len).collect();
+                self.advance(self.buffer_size);
+                if !self.send(&block, false) {
+                    return;
+                }
+            }
+            if self.jumps != jumps {
+                continue;
+            }
+
+            if done {
+                // Play out what's left, however short. The next track's audio
+                // follows straight on; without one, this is the end of the stream.
+                let tail = std::mem::take(&mut self.pending);
+                self.advance(tail.len() / self.channels);
+                if !self.send(&tail, false) {
+                    return;
+                }
+                if self.jumps != jumps {
+                    continue;
+                }
+                if let Some(track) = self.advance_tracks.then(|| self.next_track()).flatten() {
+                    self.load_track(track);
+                    continue;
+                }
+                if !self.send(&[], true) {
+                    return;
+                }
+
+                // Nothing left to read; wait for a seek, a new source, or for every sender to go away.
+                match self.commands.recv() {
+                    Ok(command) => self.handle(command),
+                    Err(_) => return,
+                }
+            }
+        }
+    }
+
+    /// Queues a block for the audio callback, waiting for room and handling
+    /// commands meanwhile. Returns false once the callback has gone away.
+    fn send(&mut self, block: &[f32], last: bool) -> bool {
+        let stamp = Stamp {
+            end: self.position as u64,
+            track: self.track,
+            playlist: self.playlist,
+            last,
+        };
+        let jumps = self.jumps;
+        // Check a few times per block played, which is how fast room appears.
+        let wait = Duration::from_secs_f64(self.buffer_size as f64 / self.output_rate / 4.0);
+
+        while !self.audio.write(block, stamp) {
+            if self.audio.is_abandoned() {
+                return false;
+            }
+            match self.commands.recv_timeout(wait) {
+                Ok(command) => {
+                    self.handle(command);
+                    if self.jumps != jumps {
+                        // The block is from before the jump, so it shouldn't be heard.
+                        return true;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => thread::sleep(wait),
+            }
+        }
+        true
+    }
+
+    fn handle(&mut self, command: Command) {
+        match command {
+            Command::Swap { source, from_start } => self.swap(source, from_start),
+            Command::SeekBy(seconds) => {
+                let played = self.playhead.load(Ordering::Relaxed) as f64;
+                let target = played + seconds * self.output_rate;
+                self.seek(target.max(0.0) as u64);
+            }
+            Command::Speed(speed) => self.set_speed(speed),
+            Command::Next => {
+                if let Some(track) = self.next_track() {
+                    self.load_track(track);
+                    self.jump(0);
+                }
+            }
+            Command::Previous => match self.open_track(self.track.saturating_sub(1)) {
+                Some(track) => {
+                    self.load_track(track);
+                    self.jump(0);
+                }
+                None => _ = self.seek(0),
+            },
+            Command::Load(paths) => self.load(&paths),
+            Command::SeekTo(seconds) => {
+                self.seek((seconds.max(0.0) * self.output_rate) as u64);
+            }
+            Command::Loop(region) => {
+                self.loop_region = region.map(|(start, end)| {
+                    (
+                        start.max(0.0) * self.output_rate,
+                        end.max(0.0) * self.output_rate,
+                    )
+                });
+                if let Some((start, end)) = self.loop_region {
+                    let played = self.playhead.load(Ordering::Relaxed);
+                    if played as f64 > end {
+                        self.seek(start as u64);
+                    } else if self.read_past(end) {
+                        // What's queued already runs past the new end, so queue it again from
+                        // the playhead to turn round there.
+                        self.seek(played);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The active loop, if any, in output-rate frames. Without a loop region,
+    /// repeating loops the whole source.
+    fn active_loop(&self) -> Option<(f64, f64)> {
+        let whole = self.repeat.then_some((0.0, f64::INFINITY));
+        self.loop_region.or(whole)
+    }
+
+    /// How many samples to read next: a block, or less to stop at the loop end.
+    fn read_len(&self, block_len: usize) -> usize {
+        let Some((_, end)) = self.active_loop() else {
+            return block_len;
+        };
+        let end = self.source_frame(end);
+        if self.read > end {
+            return block_len;
+        }
+        block_len.min(((end - self.read) as usize).saturating_mul(self.channels))
+    }
+
+    /// Whether more has been read than plays before `end`, in output-rate frames.
+    fn read_past(&self, end: f64) -> bool {
+        self.read > self.source_frame(end)
+    }
+
+    /// `frame`, in output-rate frames, at the source's rate.
+    fn source_frame(&self, frame: f64) -> u64 {
+        (frame * self.source.sample_rate() as f64 / self.output_rate) as u64
+    }
+
+    /// Notes that `frames` more source frames have gone into the stages.
+    fn count_read(&mut self, frames: usize) {
+        self.read += frames as u64;
+        let per_frame = self.output_rate / self.source.sample_rate() as f64 / self.speed.rate;
+        self.read_to += frames as f64 * per_frame;
+    }
+
+    /// Once reading reaches the loop end, or the source runs out inside the
+    /// loop, carries on reading from the loop start. Nothing is flushed or
+    /// reset, so the stages run straight across the join and playback and the
+    /// FFTs carry on without a gap. Returns true if it wrapped.
+    fn wrap_loop(&mut self, done: bool) -> bool {
+        let Some((start, end)) = self.active_loop() else {
+            return false;
+        };
+        let (start_frame, end_frame) = (self.source_frame(start), self.source_frame(end));
+        if end_frame <= start_frame || self.read > end_frame {
+            return false;
+        }
+        if self.read < end_frame && !done {
+            return false;
+        }
+        // A loop starting past the end of the source would only wrap onto nothing.
+        if self.seek_source(start as u64) != Some(start as u64) {
+            return false;
+        }
+        self.wraps.push_back((self.read_to.round() as u64, start));
+        true
+    }
+
+    /// Moves `position` past `frames` sent frames, following any wraps they pass.
+    fn advance(&mut self, frames: usize) {
+        self.sent += frames as u64;
+        self.position += frames as f64 * self.speed.rate;
+        while let Some(&(at, to)) = self.wraps.front() {
+            if at > self.sent {
+                break;
+            }
+            self.position = to + (self.sent - at) as f64 * self.speed.rate;
+            self.wraps.pop_front();
+        }
+    }
+
+    /// Expands `paths` into tracks and starts on the first, then tells the
+    /// window which playlist its blocks are stamped with from now on.
+    fn load(&mut self, paths: &[PathBuf]) {
+        let opened =
+            playlist::expand(paths).and_then(|tracks| Ok((self.open(0, &tracks[0])?, tracks)));
+        let (track, tracks) = match opened {
+            Ok(opened) => opened,
+            Err(err) => {
+                _ = self.loaded.send(Err(err));
+                return;
+            }
+        };
+        self.tracks = tracks;
+        self.playlist += 1;
+        self.load_track(track);
+        self.jump(0);
+        _ = self.loaded.send(Ok((self.playlist, self.tracks.clone())));
+    }
+
+    /// The next track that opens, skipping any that don't.
+    fn next_track(&self) -> Option<Track> {
+        (self.track + 1..self.tracks.len()).find_map(|index| self.open_track(index))
+    }
+
+    /// Opens one of the tracks, or skips it if it doesn't open.
+    fn open_track(&self, index: usize) -> Option<Track> {
+        let path = self.tracks.get(index)?;
+        self.open(index, path)
+            .map_err(|err| log::warn!("skipping {}: {err}", path.display()))
+            .ok()
+    }
+
+    /// Opens `path`, remixed to the output's channels, with stages for its rate.
+    fn open(&self, index: usize, path: &Path) -> Result<Track, Error> {
+        let source = input::open(path)?;
+        log::info!(
+            "{}: {} channels at {} Hz",
+            path.display(),
+            source.channels(),
+            source.sample_rate()
+        );
+        let source = source::fit_channels(source, self.channels);
+        let stages = Stages::new(
+            source.sample_rate() as f64,
+            self.output_rate,
+            self.channels,
+            self.buffer_size,
+            self.speed,
+        )?;
+        Ok(Track {
+            index,
+            source,
+            stages,
+        })
+    }
+
+    /// Carries on from the start of `track`. The loop points belonged to the
+    /// last track, so they go until the new track's are sent.
+    fn load_track(&mut self, track: Track) {
+        self.source = track.source;
+        self.stages = track.stages;
+        self.track = track.index;
+        self.position = 0.0;
+        self.read = 0;
+        self.read_to = self.sent as f64;
+        self.wraps.clear();
+        self.loop_region = None;
+    }
+
+    fn stages_for(&self, source: &dyn Source, speed: Speed) -> Option<Stages> {
+        Stages::new(
+            source.sample_rate() as f64,
+            self.output_rate,
+            self.channels,
+            self.buffer_size,
+            speed,
+        )
+        .map_err(|err| log::error!("{err}"))
+        .ok()
+    }
+
+    /// Rebuilds the stages for `speed` and starts again from the playhead, so
+    /// the change is heard straight away.
+    fn set_speed(&mut self, speed: Speed) {
+        let Some(stages) = self.stages_for(self.source.as_ref(), speed) else {
+            return;
+        };
+        self.stages = stages;
+        self.speed = speed;
+
+        let played = self.playhead.load(Ordering::Relaxed);
+        if !self.seek(played) {
+            // Sources that can't seek carry on from where they were read up to.
+            self.pending.clear();
+            self.read_to = self.sent as f64;
+        }
+    }
+
+    fn swap(&mut self, source: Box<dyn Source>, from_start: bool) {
+        let source = source::fit_channels(source, self.channels);
+        let Some(stages) = self.stages_for(source.as_ref(), self.speed) else {
+            return;
+        };
+
+        let played = match from_start {
+            true => 0,
+            false => self.playhead.load(Ordering::Relaxed),
+        };
+        self.source = source;
+        self.stages = stages;
+        self.read = 0;
+        if self.seek_source(played) == Some(played) {
+            self.jump(played);
+        } else {
+            // The new source is shorter, or starts at its beginning already.
+            _ = self.seek_source(0);
+            self.jump(0);
+        }
+    }
+
+    /// Moves to `played` frames at the output rate, or to the end if the
+    /// source is shorter. Returns false, leaving playback where it was, if
+    /// the source can't seek.
+    fn seek(&mut self, played: u64) -> bool {
+        let Some(played) = self.seek_source(played) else {
+            return false;
+        };
+        self.jump(played);
+        true
+    }
+
+    /// Moves the source to `played` frames at the output rate, leaving what
+    /// is queued alone, and returns where it got to.
+    fn seek_source(&mut self, played: u64) -> Option<u64> {
+        let sample_rate = self.source.sample_rate() as f64;
+        let position = (played as f64 * sample_rate / self.output_rate) as u64;
+        let reached = self.source.seek(position)?;
+        self.read = reached;
+        if reached == position {
+            Some(played)
+        } else {
+            Some((reached as f64 * self.output_rate / sample_rate) as u64)
+        }
+    }
+
+    fn jump(&mut self, played: u64) {
+        self.audio.flush();
+        self.pending.clear();
+        self.position = played as f64;
+        self.sent = 0;
+        self.read_to = 0.0;
+        self.wraps.clear();
+        self.jumps += 1;
+        self.stages.reset();
+        self.playhead.store(played, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ring::{self, Progress};
+    use clap::Parser;
+    use std::f64::consts::TAU;
+
+    /// Mono samples held in memory.
+    struct Clip {
+        sample_rate: u32,
+        samples: Vec<f32>,
+        frame: usize,
+    }
+
+    impl Source for Clip {
+        fn channels(&self) -> usize {
+            1
+        }
+
+        fn sample_rate(&self) -> u32 {
+            self.sample_rate
+        }
+
+        fn read(&mut self, len: usize) -> Vec<f32> {
+            let end = (self.frame + len).min(self.samples.len());
+            let samples = self.samples[self.frame..end].to_vec();
+            self.frame = end;
+            samples
+        }
+
+        fn seek(&mut self, frame: u64) -> Option<u64> {
+            self.frame = (frame as usize).min(self.samples.len());
+            Some(self.frame as u64)
+        }
+    }
+
+    /// The first `frames` frames played at 48 kHz from `samples` at
+    /// `sample_rate`, looping between `start` and `end` seconds.
+    fn play_loop(
+        sample_rate: u32,
+        samples: Vec<f32>,
+        (start, end): (f64, f64),
+        frames: usize,
+    ) -> Vec<f32> {
+        let args = Args::try_parse_from([
+            "weresocool_visualizer",
+            "--stdin",
+            "--buffer-size=1024",
+            "--sample-rate=48000",
+        ])
+        .unwrap();
+        let progress = Progress::default();
+        let (writer, mut reader) = ring::audio(1024 * 4, progress.clone());
+        let (s_command, r_command) = crossbeam_channel::unbounded();
+        let (s_loaded, _r_loaded) = crossbeam_channel::unbounded();
+        s_command.send(Command::Loop(Some((start, end)))).unwrap();
+        let source = Box::new(Clip {
+            sample_rate,
+            samples,
+            frame: 0,
+        });
+        let player = Player::new(
+            source,
+            vec![],
+            &args,
+            writer,
+            r_command,
+            s_loaded,
+            progress.playhead,
+        )
+        .unwrap();
+        let player = thread::spawn(move || player.run());
+
+        let mut played = vec![0.0; frames];
+        let mut filled = 0;
+        while filled < frames {
+            filled += reader.read(&mut played[filled..]);
+            thread::sleep(Duration::from_millis(1));
+        }
+        drop(reader);
+        drop(s_command);
+        player.join().unwrap();
+        played
+    }
+
+    #[test]
+    fn loops_shorter_than_a_block_turn_round_at_the_loop_points() {
+        let ramp = (0..48000).map(|frame| frame as f32).collect();
+        // Frames 480 to 600, much shorter than the 1024 frame blocks.
+        let played = play_loop(48000, ramp, (0.01, 0.0125), 10000);
+        for (frame, &sample) in played.iter().enumerate() {
+            let expected = if frame < 600 {
+                frame
+            } else {
+                480 + (frame - 600) % 120
+            };
+            assert_eq!(sample, expected as f32, "frame {frame}");
+        }
+    }
+
+    #[test]
+    fn loops_join_up_without_a_gap_when_resampling() {
+        // 25 ms of a 1 kHz sine at 44.1 kHz, then silence. The loop is ten
+        // whole cycles, so looping it should sound like the sine going on.
+        let tone = |frame: usize, rate: f64| (TAU * 1000.0 * frame as f64 / rate).sin() as f32;
+        let sine = (0..44100)
+            .map(|frame| {
+                if frame < 1102 {
+                    tone(frame, 44100.0)
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let played = play_loop(44100, sine, (0.01, 0.02), 48000);
+
+        // After the resampler has settled. Its ripple alone comes to about
+        // 0.011; restarting it at each join leaves gaps far bigger than that.
+        for (frame, &sample) in played.iter().enumerate().skip(256) {
+            let error = (sample - tone(frame, 48000.0)).abs();
+            assert!(error < 0.02, "off by {error} at frame {frame}");
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use crate::error::Error;
+use crate::input;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Turns the inputs on the command line into tracks, replacing each directory
+/// with the files in it that can be played, in name order.
+pub fn expand(inputs: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    let mut tracks = vec![];
+    for input in inputs {
+        if !input.is_dir() {
+            tracks.push(input.clone());
+            continue;
+        }
+
+        let mut files = vec![];
+        for entry in fs::read_dir(input)? {
+            let path = entry?.path();
+            if path.is_file() && input::is_supported(&path) {
+                files.push(path);
+            }
+        }
+        if files.is_empty() {
+            return Err(Error::Args(format!(
+                "no audio files in {}",
+                input.display()
+            )));
+        }
+        files.sort();
+        tracks.extend(files);
+    }
+    Ok(tracks)
+}
+
+/// The window title for `track`, counting from one.
+pub fn title(tracks: &[PathBuf], track: usize) -> String {
+    match tracks.get(track) {
+        Some(path) if tracks.len() > 1 => {
+            format!(
+                "weresoFFT - {} ({}/{})",
+                name(path),
+                track + 1,
+                tracks.len()
+            )
+        }
+        Some(path) => format!("weresoFFT - {}", name(path)),
+        None => "weresoFFT".to_string(),
+    }
+}
+
+fn name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
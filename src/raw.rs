@@ -0,0 +1,110 @@
+use crate::cli::RawFormat;
+use crate::source::Source;
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::{self, Read};
+
+/// Headerless interleaved PCM, such as a renderer piping into stdin.
+pub struct RawSource<R> {
+    reader: R,
+    format: RawFormat,
+    channels: usize,
+    sample_rate: u32,
+    ended: bool,
+}
+
+impl<R: Read> RawSource<R> {
+    pub fn new(reader: R, format: RawFormat, channels: usize, sample_rate: u32) -> Self {
+        RawSource {
+            reader,
+            format,
+            channels,
+            sample_rate,
+            ended: false,
+        }
+    }
+
+    fn read_sample(&mut self) -> io::Result<f32> {
+        let sample = match self.format {
+            RawFormat::U8 => (self.reader.read_u8()? as f32 - 128.0) / 128.0,
+            RawFormat::S16le => self.reader.read_i16::<LittleEndian>()? as f32 / 32768.0,
+            RawFormat::S24le => self.reader.read_i24::<LittleEndian>()? as f32 / 8388608.0,
+            RawFormat::S32le => self.reader.read_i32::<LittleEndian>()? as f32 / 2147483648.0,
+            RawFormat::F32le => self.reader.read_f32::<LittleEndian>()?,
+            RawFormat::F64le => self.reader.read_f64::<LittleEndian>()? as f32,
+        };
+        Ok(sample)
+    }
+}
+
+impl<R: Read + Send> Source for RawSource<R> {
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn read(&mut self, len: usize) -> Vec<f32> {
+        let mut samples = Vec::with_capacity(len);
+        while !self.ended && samples.len() < len {
+            match self.read_sample() {
+                Ok(sample) => samples.push(sample),
+                Err(err) => {
+                    if err.kind() != io::ErrorKind::UnexpectedEof {
+                        log::warn!("stopped reading input: {err}");
+                    }
+                    self.ended = true;
+                }
+            }
+        }
+
+        // A frame cut off by the end of the stream can't be played.
+        samples.truncate(samples.len() - samples.len() % self.channels);
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(bytes: &[u8], format: RawFormat, channels: usize) -> Vec<f32> {
+        RawSource::new(bytes, format, channels, 48000).read(64)
+    }
+
+    #[test]
+    fn every_format_reads_as_full_scale_floats() {
+        let cases: [(RawFormat, Vec<u8>); 6] = [
+            (RawFormat::U8, vec![0, 128, 192]),
+            (
+                RawFormat::S16le,
+                [i16::MIN, 0, 1 << 14].map(i16::to_le_bytes).concat(),
+            ),
+            (RawFormat::S24le, vec![0, 0, 0x80, 0, 0, 0, 0, 0, 0x40]),
+            (
+                RawFormat::S32le,
+                [i32::MIN, 0, 1 << 30].map(i32::to_le_bytes).concat(),
+            ),
+            (
+                RawFormat::F32le,
+                [-1.0f32, 0.0, 0.5].map(f32::to_le_bytes).concat(),
+            ),
+            (
+                RawFormat::F64le,
+                [-1.0f64, 0.0, 0.5].map(f64::to_le_bytes).concat(),
+            ),
+        ];
+        for (format, bytes) in cases {
+            assert_eq!(read_all(&bytes, format, 1), [-1.0, 0.0, 0.5], "{format:?}");
+        }
+    }
+
+    #[test]
+    fn a_frame_cut_off_by_the_end_is_dropped() {
+        let bytes = [1i16, 2, 3].map(i16::to_le_bytes).concat();
+        let mut source = RawSource::new(&bytes[..], RawFormat::S16le, 2, 48000);
+        assert_eq!(source.read(4).len(), 2);
+        assert!(source.read(4).is_empty());
+    }
+}
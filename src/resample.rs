@@ -0,0 +1,145 @@
+use crate::error::Error;
+use crate::mix;
+use rubato::{
+    Resampler as _, SincFixedIn, SincInterpolationParameters, SincInterpolationType, WindowFunction,
+};
+
+/// Band-limited conversion of interleaved audio from the file's sample rate
+/// to the rate of the output stream.
+pub struct Resampler {
+    inner: SincFixedIn<f32>,
+    channels: usize,
+    ratio: f64,
+    input: Vec<f32>,
+    frames_in: usize,
+    frames_out: usize,
+}
+
+impl Resampler {
+    pub fn new(
+        from_rate: f64,
+        to_rate: f64,
+        channels: usize,
+        chunk_size: usize,
+    ) -> Result<Self, Error> {
+        let parameters = SincInterpolationParameters {
+            sinc_len: 256,
+            f_cutoff: 0.95,
+            oversampling_factor: 256,
+            interpolation: SincInterpolationType::Cubic,
+            window: WindowFunction::BlackmanHarris2,
+        };
+        let ratio = to_rate / from_rate;
+        let inner = SincFixedIn::new(ratio, 1.0, parameters, chunk_size, channels)?;
+
+        Ok(Resampler {
+            inner,
+            channels,
+            ratio,
+            input: vec![],
+            frames_in: 0,
+            frames_out: 0,
+        })
+    }
+
+    /// Returns `None` when no conversion is needed.
+    pub fn for_rates(
+        from_rate: f64,
+        to_rate: f64,
+        channels: usize,
+        chunk_size: usize,
+    ) -> Result<Option<Self>, Error> {
+        if from_rate == to_rate {
+            return Ok(None);
+        }
+        log::info!("resampling from {from_rate} Hz to {to_rate} Hz");
+        Ok(Some(Resampler::new(
+            from_rate, to_rate, channels, chunk_size,
+        )?))
+    }
+
+    /// Takes interleaved samples at the source rate and returns whatever
+    /// interleaved output at the target rate is ready so far. `SincFixedIn`
+    /// centres its first output on the first input frame, so there is no
+    /// filter delay to skip.
+    pub fn process(&mut self, interleaved: &[f32]) -> Result<Vec<f32>, Error> {
+        self.input.extend_from_slice(interleaved);
+        self.frames_in += interleaved.len() / self.channels;
+
+        let mut output = vec![];
+        loop {
+            let needed = self.inner.input_frames_next() * self.channels;
+            if self.input.len() < needed {
+                break;
+            }
+            let chunk: Vec<f32> = self.input.drain(..needed).collect();
+            let waves = self
+                .inner
+                .process(&mix::deinterleave(&chunk, self.channels), None)?;
+            self.push_output(waves, &mut output);
+        }
+        Ok(output)
+    }
+
+    /// Resamples whatever is still buffered and drains the filter, so the
+    /// output ends where the input did.
+    pub fn flush(&mut self) -> Result<Vec<f32>, Error> {
+        let mut output = vec![];
+        let rest = mix::deinterleave(&std::mem::take(&mut self.input), self.channels);
+        let waves = self.inner.process_partial(Some(&rest), None)?;
+        self.push_output(waves, &mut output);
+
+        let expected = (self.frames_in as f64 * self.ratio).round() as usize;
+        while self.frames_out < expected {
+            let waves = self.inner.process_partial(None::<&[Vec<f32>]>, None)?;
+            self.push_output(waves, &mut output);
+        }
+
+        let excess = self.frames_out.saturating_sub(expected);
+        output.truncate(output.len().saturating_sub(excess * self.channels));
+        self.frames_out = expected;
+        Ok(output)
+    }
+
+    /// Forgets buffered input and filter state, for jumping to a new position.
+    pub fn reset(&mut self) {
+        self.inner.reset();
+        self.input.clear();
+        self.frames_in = 0;
+        self.frames_out = 0;
+    }
+
+    fn push_output(&mut self, waves: Vec<Vec<f32>>, output: &mut Vec<f32>) {
+        self.frames_out += waves[0].len();
+        mix::interleave(&waves, output);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_lines_up_with_the_input_and_ends_with_it() {
+        let mut resampler = Resampler::new(44100.0, 48000.0, 2, 1024).unwrap();
+        let mut input = vec![0.0; 4410 * 2];
+        input[1000 * 2] = 1.0;
+        input[1000 * 2 + 1] = 1.0;
+
+        let mut output = vec![];
+        for chunk in input.chunks(1024 * 2) {
+            output.extend(resampler.process(chunk).unwrap());
+        }
+        output.extend(resampler.flush().unwrap());
+
+        assert_eq!(output.len(), 4800 * 2);
+        let peak = (0..output.len() / 2)
+            .max_by(|&a, &b| output[a * 2].total_cmp(&output[b * 2]))
+            .unwrap();
+        let expected = (1000.0 * 48000.0 / 44100.0) as usize;
+        assert!(
+            peak.abs_diff(expected) <= 1,
+            "peak at {peak}, expected {expected}"
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+const MAX_BLOCKS: usize = 64;
+
+/// What the audio callback has played so far, for everything else to watch.
+#[derive(Clone, Default)]
+pub struct Progress {
+    /// Source frames at the output rate.
+    pub playhead: Arc<AtomicU64>,
+    /// Index of the track being heard.
+    pub track: Arc<AtomicUsize>,
+    /// Which list of tracks that index is into; it changes when files are dropped on the window.
+    /// Stored after `track`, so a reader that sees a new playlist sees its track too.
+    pub playlist: Arc<AtomicUsize>,
+    /// Times the audio ran dry mid-stream, counted once it comes back.
+    pub underruns: Arc<AtomicU64>,
+    /// Set once the last block of the source has played, until more audio arrives.
+    pub ended: Arc<AtomicBool>,
+}
+
+/// Where playback has got to once a block has been heard.
+#[derive(Clone, Copy, Debug)]
+pub struct Stamp {
+    /// The playhead.
+    pub end: u64,
+    pub track: usize,
+    pub playlist: usize,
+    /// Nothing follows; the block may be shorter than the rest, or empty.
+    pub last: bool,
+}
+
+/// Marks out one written block: how many of its samples are still to be
+/// read, which flush it belongs to, and where it leaves playback.
+#[derive(Clone, Copy)]
+struct Marker {
+    samples: usize,
+    generation: u64,
+    stamp: Stamp,
+}
+
+/// A lock-free queue of interleaved audio with room for `capacity` samples,
+/// from the thread producing it to the audio callback.
+pub fn audio(capacity: usize, progress: Progress) -> (AudioWriter, AudioReader) {
+    let (s_samples, r_samples) = RingBuffer::new(capacity);
+    let (s_markers, r_markers) = RingBuffer::new(MAX_BLOCKS);
+    let generation = Arc::new(AtomicU64::new(0));
+
+    let writer = AudioWriter {
+        samples: s_samples,
+        markers: s_markers,
+        generation: Arc::clone(&generation),
+    };
+    let reader = AudioReader {
+        samples: r_samples,
+        markers: r_markers,
+        generation,
+        current: None,
+        progress,
+        starved: true,
+        gap: false,
+        // Waiting for the first block isn't an underrun.
+        heard: None,
+    };
+    (writer, reader)
+}
+
+pub struct AudioWriter {
+    samples: Producer<f32>,
+    markers: Producer<Marker>,
+    generation: Arc<AtomicU64>,
+}
+
+impl AudioWriter {
+    /// Queues all of `block`, or nothing if there isn't room for it. Never blocks.
+    pub fn write(&mut self, block: &[f32], stamp: Stamp) -> bool {
+        if self.samples.slots() < block.len() || self.markers.is_full() {
+            return false;
+        }
+        let Ok(chunk) = self.samples.write_chunk_uninit(block.len()) else {
+            return false;
+        };
+        chunk.fill_from_iter(block.iter().copied());
+
+        let marker = Marker {
+            samples: block.len(),
+            generation: self.generation.load(Ordering::Relaxed),
+            stamp,
+        };
+        self.markers.push(marker).is_ok()
+    }
+
+    /// Makes the reader skip everything written so far, instead of playing it.
+    pub fn flush(&self) {
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+
+    /// Counts the flushes so far, for anything keeping its own state about what was queued.
+    pub fn flushes(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.generation)
+    }
+
+    /// True once the audio callback has gone away.
+    pub fn is_abandoned(&self) -> bool {
+        self.samples.is_abandoned()
+    }
+}
+
+pub struct AudioReader {
+    samples: Consumer<f32>,
+    markers: Consumer<Marker>,
+    generation: Arc<AtomicU64>,
+    /// The block being read, if it was only partly read last time.
+    current: Option<Marker>,
+    progress: Progress,
+    starved: bool,
+    /// Ran dry since the last block started, which is an underrun unless the
+    /// next block starts something new.
+    gap: bool,
+    /// The generation, playlist and track of the last block started.
+    heard: Option<(u64, usize, usize)>,
+}
+
+impl AudioReader {
+    /// Fills the start of `out` with whatever is queued and returns how many
+    /// samples that was, counting an underrun if it ran out. Never blocks or
+    /// allocates, so it is safe on the audio thread.
+    pub fn read(&mut self, out: &mut [f32]) -> usize {
+        let generation = self.generation.load(Ordering::Acquire);
+        let mut filled = 0;
+        let mut ended = false;
+
+        while filled < out.len() {
+            let (mut marker, started) = match self.current.take() {
+                Some(marker) => (marker, false),
+                None => match self.markers.pop() {
+                    Ok(marker) => (marker, true),
+                    Err(_) => break,
+                },
+            };
+            if marker.generation < generation {
+                self.skip(marker.samples);
+                continue;
+            }
+            if started {
+                self.start(&marker);
+            }
+
+            let n = marker.samples.min(out.len() - filled);
+            let Ok(chunk) = self.samples.read_chunk(n) else {
+                break;
+            };
+            let (first, second) = chunk.as_slices();
+            out[filled..filled + first.len()].copy_from_slice(first);
+            out[filled + first.len()..filled + n].copy_from_slice(second);
+            chunk.commit_all();
+            filled += n;
+
+            if n > 0 {
+                self.progress.ended.store(false, Ordering::Relaxed);
+            }
+
+            marker.samples -= n;
+            if marker.samples > 0 {
+                self.current = Some(marker);
+                continue;
+            }
+            let stamp = marker.stamp;
+            self.progress.playhead.store(stamp.end, Ordering::Relaxed);
+            self.progress.track.store(stamp.track, Ordering::Relaxed);
+            self.progress
+                .playlist
+                .store(stamp.playlist, Ordering::Release);
+            if stamp.last {
+                self.progress.ended.store(true, Ordering::Relaxed);
+                ended = true;
+            }
+        }
+
+        // Running dry after the end is expected, not an underrun.
+        let short = filled < out.len();
+        if short && !self.starved && !ended {
+            self.gap = true;
+        }
+        self.starved = short || ended;
+        filled
+    }
+
+    /// Counts the gap before `marker` as an underrun, unless it is the first
+    /// block after a flush or of another track: refilling after a seek, or
+    /// opening the next track, isn't playback falling behind.
+    fn start(&mut self, marker: &Marker) {
+        let heard = (marker.generation, marker.stamp.playlist, marker.stamp.track);
+        if self.gap && self.heard == Some(heard) {
+            self.progress.underruns.fetch_add(1, Ordering::Relaxed);
+        }
+        self.gap = false;
+        self.heard = Some(heard);
+    }
+
+    fn skip(&mut self, samples: usize) {
+        if let Ok(chunk) = self.samples.read_chunk(samples) {
+            chunk.commit_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stamp(track: usize, last: bool) -> Stamp {
+        Stamp {
+            end: 0,
+            track,
+            playlist: 0,
+            last,
+        }
+    }
+
+    /// Writes a block for `track` and reads all of it.
+    fn play(writer: &mut AudioWriter, reader: &mut AudioReader, track: usize) {
+        assert!(writer.write(&[0.5; 4], stamp(track, false)));
+        assert_eq!(reader.read(&mut [0.0; 4]), 4);
+    }
+
+    /// Reads from an empty queue.
+    fn run_dry(reader: &mut AudioReader) {
+        assert_eq!(reader.read(&mut [0.0; 4]), 0);
+    }
+
+    fn underruns(progress: &Progress) -> u64 {
+        progress.underruns.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn running_dry_mid_track_is_an_underrun() {
+        let progress = Progress::default();
+        let (mut writer, mut reader) = audio(64, progress.clone());
+        run_dry(&mut reader);
+        play(&mut writer, &mut reader, 0);
+        assert_eq!(underruns(&progress), 0);
+        run_dry(&mut reader);
+        run_dry(&mut reader);
+        play(&mut writer, &mut reader, 0);
+        assert_eq!(underruns(&progress), 1);
+    }
+
+    #[test]
+    fn refilling_after_a_flush_is_not_an_underrun() {
+        let progress = Progress::default();
+        let (mut writer, mut reader) = audio(64, progress.clone());
+        play(&mut writer, &mut reader, 0);
+        writer.flush();
+        run_dry(&mut reader);
+        play(&mut writer, &mut reader, 0);
+        assert_eq!(underruns(&progress), 0);
+    }
+
+    #[test]
+    fn waiting_for_the_next_track_is_not_an_underrun() {
+        let progress = Progress::default();
+        let (mut writer, mut reader) = audio(64, progress.clone());
+        play(&mut writer, &mut reader, 0);
+        run_dry(&mut reader);
+        play(&mut writer, &mut reader, 1);
+        assert_eq!(underruns(&progress), 0);
+        assert_eq!(progress.track.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn running_dry_at_the_end_is_not_an_underrun() {
+        let progress = Progress::default();
+        let (mut writer, mut reader) = audio(64, progress.clone());
+        play(&mut writer, &mut reader, 0);
+        assert!(writer.write(&[], stamp(0, true)));
+        run_dry(&mut reader);
+        assert!(progress.ended.load(Ordering::Relaxed));
+        run_dry(&mut reader);
+        play(&mut writer, &mut reader, 0);
+        assert_eq!(underruns(&progress), 0);
+    }
+}
@@ -0,0 +1,101 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Settings remembered between runs, stored as `key = value` lines.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Session {
+    pub loop_start: Option<f64>,
+    pub loop_end: Option<f64>,
+}
+
+impl Session {
+    /// The session file kept beside an input file.
+    pub fn path_for(input: &Path) -> PathBuf {
+        let mut path = input.as_os_str().to_owned();
+        path.push(".session");
+        PathBuf::from(path)
+    }
+
+    /// Reads a session, starting empty if the file doesn't exist yet.
+    pub fn load(path: &Path) -> Session {
+        let mut session = Session::default();
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => return session,
+        };
+
+        for line in contents.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim().parse::<f64>().ok();
+            match key.trim() {
+                "loop_start" => session.loop_start = value,
+                "loop_end" => session.loop_end = value,
+                other => log::warn!("ignoring unknown session key {other} in {}", path.display()),
+            }
+        }
+        session
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut contents = String::new();
+        if let Some(loop_start) = self.loop_start {
+            contents.push_str(&format!("loop_start = {loop_start}\n"));
+        }
+        if let Some(loop_end) = self.loop_end {
+            contents.push_str(&format!("loop_end = {loop_end}\n"));
+        }
+        fs::write(path, contents)
+    }
+
+    /// The loop region, once both ends are set the right way round.
+    pub fn loop_region(&self) -> Option<(f64, f64)> {
+        match (self.loop_start, self.loop_end) {
+            (Some(start), Some(end)) if start < end => Some((start, end)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn sessions_come_back_as_they_were_saved() {
+        let path = env::temp_dir().join(format!("visualizer-{}.session", std::process::id()));
+        let session = Session {
+            loop_start: Some(1.5),
+            loop_end: Some(4.25),
+        };
+        session.save(&path).unwrap();
+        let loaded = Session::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, session);
+        assert_eq!(Session::load(&path), Session::default());
+    }
+
+    #[test]
+    fn the_session_file_sits_beside_the_input() {
+        let path = Session::path_for(Path::new("renders/piece.wav"));
+        assert_eq!(path, Path::new("renders/piece.wav.session"));
+    }
+
+    #[test]
+    fn a_loop_needs_both_ends_the_right_way_round() {
+        let session = |loop_start, loop_end| Session {
+            loop_start,
+            loop_end,
+        };
+        assert_eq!(
+            session(Some(1.0), Some(2.0)).loop_region(),
+            Some((1.0, 2.0))
+        );
+        assert_eq!(session(Some(2.0), Some(1.0)).loop_region(), None);
+        assert_eq!(session(Some(1.0), None).loop_region(), None);
+    }
+}
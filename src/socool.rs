@@ -0,0 +1,22 @@
+use crate::error::Error;
+use std::path::{Path, PathBuf};
+use weresocool::generation::{RenderReturn, RenderType, WavType};
+use weresocool::interpretable::{InputType::Filename, Interpretable};
+
+/// Renders a composition through the WereSoCool engine into the bytes of a wav file.
+pub fn render(path: &Path) -> Result<Vec<u8>, Error> {
+    let filename = path
+        .to_str()
+        .ok_or_else(|| Error::Args(format!("{} is not valid utf-8", path.display())))?;
+    let working_path = path.parent().map(Path::to_path_buf);
+
+    let render = RenderType::Wav(WavType::Wav {
+        cli: false,
+        output_dir: PathBuf::new(),
+    });
+    match Filename(filename).make(render, working_path) {
+        Ok(RenderReturn::Wav(bytes)) => Ok(bytes),
+        Ok(_) => Err(Error::Socool("renderer did not return a wav".to_string())),
+        Err(err) => Err(Error::Socool(format!("{err:?}"))),
+    }
+}
@@ -0,0 +1,56 @@
+use crate::mix;
+
+/// Anything the player can stream interleaved f32 frames from.
+pub trait Source: Send {
+    fn channels(&self) -> usize;
+
+    fn sample_rate(&self) -> u32;
+
+    /// Reads up to `len` interleaved samples. A short read means the stream has ended.
+    fn read(&mut self, len: usize) -> Vec<f32>;
+
+    /// Moves to `frame`, or to the end if the source is shorter than that, and
+    /// returns the frame it got to. `None` if the source can't seek.
+    fn seek(&mut self, _frame: u64) -> Option<u64> {
+        None
+    }
+}
+
+/// Presents a source with a different number of channels, so every track in
+/// a playlist fits the same output and FFT lanes.
+struct Remix {
+    inner: Box<dyn Source>,
+    channels: usize,
+}
+
+impl Source for Remix {
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn read(&mut self, len: usize) -> Vec<f32> {
+        let from = self.inner.channels();
+        let samples = self.inner.read(len / self.channels * from);
+        mix::remix(&samples, from, self.channels)
+    }
+
+    fn seek(&mut self, frame: u64) -> Option<u64> {
+        self.inner.seek(frame)
+    }
+}
+
+/// Wraps `source` to have `channels` channels, if it doesn't already.
+pub fn fit_channels(source: Box<dyn Source>, channels: usize) -> Box<dyn Source> {
+    if source.channels() == channels {
+        source
+    } else {
+        Box::new(Remix {
+            inner: source,
+            channels,
+        })
+    }
+}
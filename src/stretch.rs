@@ -0,0 +1,206 @@
+use std::f32::consts::PI;
+
+/// Seconds of audio in each overlapping grain.
+const GRAIN_SECONDS: f64 = 0.04;
+/// How far, in seconds, a grain may move from its nominal position to line up with the last one.
+const TOLERANCE_SECONDS: f64 = 0.005;
+
+/// Changes the tempo of interleaved audio without changing its pitch, using
+/// WSOLA: Hann-windowed grains are overlap-added at a fixed hop, each taken
+/// from wherever near its nominal position best continues the previous grain.
+pub struct Stretcher {
+    channels: usize,
+    tempo: f64,
+    grain: usize,
+    hop: usize,
+    tolerance: usize,
+    window: Vec<f32>,
+    input: Vec<f32>,
+    /// Frames into `input` where the next grain nominally starts.
+    position: f64,
+    /// What followed the previous grain in the input, which the next one should resemble.
+    continuation: Option<Vec<f32>>,
+    /// The second half of the previous windowed grain, waiting to be overlap-added.
+    overlap: Vec<f32>,
+    frames_in: usize,
+    frames_out: usize,
+}
+
+impl Stretcher {
+    /// Plays `tempo` times as fast as the input at `sample_rate`.
+    pub fn new(tempo: f64, channels: usize, sample_rate: f64) -> Self {
+        let hop = ((GRAIN_SECONDS * sample_rate) as usize / 2).max(1);
+        let grain = hop * 2;
+        let window = (0..grain)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / grain as f32).cos())
+            .collect();
+
+        Stretcher {
+            channels,
+            tempo,
+            grain,
+            hop,
+            tolerance: (TOLERANCE_SECONDS * sample_rate) as usize,
+            window,
+            input: vec![],
+            position: 0.0,
+            continuation: None,
+            overlap: vec![0.0; hop * channels],
+            frames_in: 0,
+            frames_out: 0,
+        }
+    }
+
+    /// Takes interleaved input and returns whatever stretched output is ready so far.
+    pub fn process(&mut self, interleaved: &[f32]) -> Vec<f32> {
+        self.input.extend_from_slice(interleaved);
+        self.frames_in += interleaved.len() / self.channels;
+
+        let mut output = vec![];
+        loop {
+            let nominal = self.position.round() as usize;
+            if (nominal + self.tolerance + self.grain) * self.channels > self.input.len() {
+                break;
+            }
+            let start = self.best_start(nominal);
+            self.add_grain(start, &mut output);
+            self.position += self.hop as f64 * self.tempo;
+
+            let consumed = (self.position as usize).saturating_sub(self.tolerance);
+            self.input.drain(..consumed * self.channels);
+            self.position -= consumed as f64;
+        }
+        self.frames_out += output.len() / self.channels;
+        output
+    }
+
+    /// Stretches whatever is still buffered, so the output ends where the input did.
+    pub fn flush(&mut self) -> Vec<f32> {
+        let expected = (self.frames_in as f64 / self.tempo).round() as usize;
+        let keep = expected.saturating_sub(self.frames_out);
+
+        let padding = vec![0.0; (self.grain + 2 * self.tolerance) * self.channels];
+        let mut output = self.process(&padding);
+        output.extend_from_slice(&self.overlap);
+        output.truncate((keep * self.channels).min(output.len()));
+        self.reset();
+        output
+    }
+
+    /// Forgets buffered input and the previous grain, for jumping to a new position.
+    pub fn reset(&mut self) {
+        self.input.clear();
+        self.position = 0.0;
+        self.continuation = None;
+        self.overlap.fill(0.0);
+        self.frames_in = 0;
+        self.frames_out = 0;
+    }
+
+    /// The start near `nominal` whose first half best matches the continuation
+    /// of the previous grain, by normalized cross-correlation of the channel sum.
+    fn best_start(&self, nominal: usize) -> usize {
+        let Some(continuation) = &self.continuation else {
+            return nominal;
+        };
+
+        let low = nominal.saturating_sub(self.tolerance);
+        let high = nominal + self.tolerance;
+        let mut best = (nominal, f32::MIN);
+        for start in low..=high {
+            let mut correlation = 0.0;
+            let mut energy = 0.0;
+            for (frame, target) in continuation.iter().enumerate() {
+                let at = (start + frame) * self.channels;
+                let sample: f32 = self.input[at..at + self.channels].iter().sum();
+                correlation += sample * target;
+                energy += sample * sample;
+            }
+            let score = correlation / energy.sqrt().max(f32::EPSILON);
+            if score > best.1 {
+                best = (start, score);
+            }
+        }
+        best.0
+    }
+
+    fn add_grain(&mut self, start: usize, output: &mut Vec<f32>) {
+        let grain = &self.input[start * self.channels..(start + self.grain) * self.channels];
+        let windowed: Vec<f32> = grain
+            .chunks_exact(self.channels)
+            .zip(&self.window)
+            .flat_map(|(frame, w)| frame.iter().map(move |s| s * w))
+            .collect();
+
+        let (first, second) = windowed.split_at(self.hop * self.channels);
+        output.extend(first.iter().zip(&self.overlap).map(|(a, b)| a + b));
+        self.overlap.copy_from_slice(second);
+
+        let next = (start + self.hop) * self.channels;
+        self.continuation = Some(
+            self.input[next..next + self.hop * self.channels]
+                .chunks_exact(self.channels)
+                .map(|frame| frame.iter().sum())
+                .collect(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f64 = 48000.0;
+
+    /// Stretches a stereo sine at `hz` in blocks the way the player does.
+    fn stretch(tempo: f64, hz: f64, frames: usize) -> Vec<f32> {
+        let input: Vec<f32> = (0..frames)
+            .flat_map(|n| {
+                let sample = (2.0 * std::f64::consts::PI * hz * n as f64 / RATE).sin() as f32;
+                [sample, sample]
+            })
+            .collect();
+        let mut stretcher = Stretcher::new(tempo, 2, RATE);
+        let mut output = vec![];
+        for block in input.chunks(1024 * 2) {
+            output.extend(stretcher.process(block));
+        }
+        output.extend(stretcher.flush());
+        output
+    }
+
+    /// The frequency of the left channel, from the cycles between its first
+    /// and last rising zero crossings.
+    fn frequency(interleaved: &[f32]) -> f64 {
+        let left: Vec<f32> = interleaved.iter().step_by(2).copied().collect();
+        let rising: Vec<usize> = (1..left.len())
+            .filter(|&n| left[n - 1] < 0.0 && left[n] >= 0.0)
+            .collect();
+        let cycles = rising.len() - 1;
+        cycles as f64 * RATE / (rising[cycles] - rising[0]) as f64
+    }
+
+    #[test]
+    fn the_output_ends_where_the_input_did() {
+        for (tempo, frames) in [(0.5, 9600), (2.0, 9600), (2.0, 9601), (0.75, 7001)] {
+            let output = stretch(tempo, 440.0, frames);
+            let expected = (frames as f64 / tempo).round() as usize;
+            assert_eq!(output.len(), expected * 2, "{tempo}x of {frames} frames");
+        }
+    }
+
+    #[test]
+    fn a_steady_sine_keeps_its_pitch() {
+        for tempo in [0.5, 2.0] {
+            let output = stretch(tempo, 440.0, 14400);
+            // Away from the fade in at the start and the padding at the end.
+            let frames = output.len() / 2;
+            let middle = &output[frames / 4 * 2..frames * 3 / 4 * 2];
+            let hz = frequency(middle);
+            assert!(
+                (hz - 440.0).abs() < 440.0 * 0.01,
+                "{tempo}x played at {hz} Hz"
+            );
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use crate::player::{Command, Speed};
+use crate::session::Session;
+use crossbeam_channel::Sender;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use winit::event::VirtualKeyCode;
+use winit_input_helper::WinitInputHelper;
+
+pub const MIN_SPEED: f64 = 0.25;
+pub const MAX_SPEED: f64 = 2.0;
+const SPEEDS: [f64; 8] = [MIN_SPEED, 0.5, 0.75, 0.9, 1.0, 1.25, 1.5, MAX_SPEED];
+/// How long a refused seek or loop is pointed out for.
+const NOTICE: Duration = Duration::from_secs(2);
+
+/// Keyboard control of playback: space pauses, left/right and down/up seek
+/// by the short and long steps, home restarts, and the bracket keys set the
+/// loop in and out points, with backslash clearing them. Minus and equals step
+/// the speed down and up, zero resets it and P toggles keeping the pitch.
+/// Page down and page up skip to the next and previous track.
+pub struct Transport {
+    paused: Arc<AtomicBool>,
+    commands: Sender<Command>,
+    playhead: Arc<AtomicU64>,
+    output_rate: f64,
+    /// The lowest and highest frequencies on screen, in Hz.
+    shown: (f64, f64),
+    seek_step: f64,
+    long_seek_step: f64,
+    speed: Speed,
+    session: Session,
+    session_path: Option<PathBuf>,
+    seekable: bool,
+    /// When a seek was last refused.
+    refused: Option<Instant>,
+}
+
+impl Transport {
+    pub fn new(
+        commands: Sender<Command>,
+        playhead: Arc<AtomicU64>,
+        output_rate: f64,
+        shown: (f64, f64),
+        seek_step: f64,
+        long_seek_step: f64,
+        speed: Speed,
+    ) -> Self {
+        if speed != Speed::default() {
+            _ = commands.send(Command::Speed(speed));
+        }
+        Transport {
+            paused: Arc::new(AtomicBool::new(false)),
+            commands,
+            playhead,
+            output_rate,
+            shown,
+            seek_step,
+            long_seek_step,
+            speed,
+            session: Session::default(),
+            session_path: None,
+            seekable: true,
+            refused: None,
+        }
+    }
+
+    /// For input that can only be played straight through, like stdin: seeks
+    /// and loop points are refused and say so on the status line, rather
+    /// than doing nothing.
+    pub fn without_seeking(&mut self) {
+        self.seekable = false;
+    }
+
+    /// Restores the loop points saved in `path`, replacing any from the last
+    /// track, and saves any changes back to it.
+    pub fn load_session(&mut self, path: PathBuf) {
+        self.session = Session::load(&path);
+        self.session_path = Some(path);
+        self.send(Command::Loop(self.session.loop_region()));
+    }
+
+    /// Shared with the audio callback, which outputs silence and holds its position while set.
+    pub fn paused(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.paused)
+    }
+
+    /// The frequencies in the source that end up on screen, when tape-style
+    /// speed has moved them away from those shown.
+    pub fn source_range(&self) -> Option<(f64, f64)> {
+        let (low, high) = self.shown;
+        let rate = self.speed.rate;
+        (rate != 1.0 && !self.speed.preserve_pitch).then_some((low / rate, high / rate))
+    }
+
+    /// A line describing the speed and loop points, if either is set.
+    pub fn status(&self) -> Option<String> {
+        let mut parts = vec![];
+        if self.refused.is_some_and(|at| at.elapsed() < NOTICE) {
+            parts.push("CAN'T SEEK OR LOOP THIS INPUT".to_string());
+        }
+        if self.speed.rate != 1.0 {
+            let pitch = match self.source_range() {
+                // Everything on screen moves by the same interval as what's heard.
+                Some((low, high)) => format!(
+                    "PITCH {:+.1} ST, SOURCE {:.0}-{:.0} HZ",
+                    12.0 * self.speed.rate.log2(),
+                    low,
+                    high
+                ),
+                None => "PITCH KEPT".to_string(),
+            };
+            parts.push(format!("SPEED {:.2}x ({pitch})", self.speed.rate));
+        }
+        match (self.session.loop_start, self.session.loop_end) {
+            (None, None) => {}
+            (start, end) => {
+                let show = |time: Option<f64>| time.map_or("--".to_string(), timestamp);
+                parts.push(format!("LOOP {} - {}", show(start), show(end)));
+            }
+        }
+        (!parts.is_empty()).then(|| parts.join("   "))
+    }
+
+    pub fn handle_input(&mut self, input: &WinitInputHelper) {
+        if input.key_pressed(VirtualKeyCode::Space) {
+            self.paused.fetch_xor(true, Ordering::Relaxed);
+        }
+        if input.key_pressed(VirtualKeyCode::Home) {
+            self.seek(Command::SeekTo(0.0));
+        }
+        if input.key_pressed(VirtualKeyCode::PageDown) {
+            self.send(Command::Next);
+        }
+        if input.key_pressed(VirtualKeyCode::PageUp) {
+            self.send(Command::Previous);
+        }
+
+        let steps = [
+            (VirtualKeyCode::Left, -self.seek_step),
+            (VirtualKeyCode::Right, self.seek_step),
+            (VirtualKeyCode::Down, -self.long_seek_step),
+            (VirtualKeyCode::Up, self.long_seek_step),
+        ];
+        for (key, seconds) in steps {
+            if input.key_pressed(key) {
+                self.seek(Command::SeekBy(seconds));
+            }
+        }
+
+        let mut speed = self.speed;
+        if input.key_pressed(VirtualKeyCode::Minus) {
+            speed.rate = SPEEDS
+                .iter()
+                .rev()
+                .find(|&&rate| rate < speed.rate)
+                .map_or(MIN_SPEED, |&rate| rate);
+        }
+        if input.key_pressed(VirtualKeyCode::Equals) {
+            speed.rate = SPEEDS
+                .iter()
+                .find(|&&rate| rate > speed.rate)
+                .map_or(MAX_SPEED, |&rate| rate);
+        }
+        if input.key_pressed(VirtualKeyCode::Key0) {
+            speed.rate = 1.0;
+        }
+        if input.key_pressed(VirtualKeyCode::P) {
+            speed.preserve_pitch = !speed.preserve_pitch;
+        }
+        if speed != self.speed {
+            self.speed = speed;
+            self.send(Command::Speed(speed));
+        }
+
+        let loop_keys = [
+            VirtualKeyCode::LBracket,
+            VirtualKeyCode::RBracket,
+            VirtualKeyCode::Backslash,
+        ];
+        if !self.seekable && loop_keys.iter().any(|&key| input.key_pressed(key)) {
+            self.refused = Some(Instant::now());
+            return;
+        }
+        if input.key_pressed(VirtualKeyCode::LBracket) {
+            self.session.loop_start = Some(self.position());
+            self.loop_changed();
+        }
+        if input.key_pressed(VirtualKeyCode::RBracket) {
+            self.session.loop_end = Some(self.position());
+            self.loop_changed();
+        }
+        if input.key_pressed(VirtualKeyCode::Backslash) {
+            self.session.loop_start = None;
+            self.session.loop_end = None;
+            self.loop_changed();
+        }
+    }
+
+    /// Seconds into the source at the playhead.
+    fn position(&self) -> f64 {
+        self.playhead.load(Ordering::Relaxed) as f64 / self.output_rate
+    }
+
+    fn loop_changed(&self) {
+        self.send(Command::Loop(self.session.loop_region()));
+        if let Some(path) = &self.session_path {
+            if let Err(err) = self.session.save(path) {
+                log::warn!("could not save session to {}: {err}", path.display());
+            }
+        }
+    }
+
+    fn seek(&mut self, command: Command) {
+        if self.seekable {
+            self.send(command);
+        } else {
+            self.refused = Some(Instant::now());
+        }
+    }
+
+    // Nothing is listening in capture mode, so the send fails and the command is dropped.
+    fn send(&self, command: Command) {
+        _ = self.commands.send(command);
+    }
+}
+
+fn timestamp(seconds: f64) -> String {
+    let minutes = (seconds / 60.0).floor();
+    format!("{}:{:04.1}", minutes, seconds - minutes * 60.0)
+}
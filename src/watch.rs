@@ -0,0 +1,175 @@
+use crate::error::Error;
+use crate::input;
+use crate::overlay::Overlay;
+use crate::player::Command;
+use crate::source::Source;
+use crossbeam_channel::{self as channel, Sender};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+const DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// Stops watching when dropped.
+pub struct Watch {
+    _watcher: RecommendedWatcher,
+}
+
+/// Re-opens `path` every time it changes on disk and hands the result to the player,
+/// which carries on from the playhead unless `from_start` is set.
+/// Failures are shown on the overlay and the previous audio keeps playing.
+pub fn spawn(
+    path: PathBuf,
+    from_start: bool,
+    commands: Sender<Command>,
+    overlay: Overlay,
+) -> Result<Watch, Error> {
+    let (s_events, r_events) = channel::unbounded();
+    let mut watcher = notify::recommended_watcher(s_events)?;
+    // Editors often save by renaming a temp file over the original, which
+    // drops a watch on the file itself, so watch its directory instead.
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+    // Dropping the watcher drops its sender, which ends the loop.
+    thread::spawn(move || {
+        while let Ok(event) = r_events.recv() {
+            if !touches(&event, &path) {
+                continue;
+            }
+            while r_events.recv_timeout(DEBOUNCE).is_ok() {}
+            // A render that pauses mid-write looks finished; the next write wakes this up again.
+            if !is_complete(&path) {
+                log::info!("waiting for {} to be finished", path.display());
+                continue;
+            }
+
+            match reload(&path) {
+                Ok(source) => {
+                    overlay.clear();
+                    if commands.send(Command::Swap { source, from_start }).is_err() {
+                        return;
+                    }
+                }
+                Err(err) => overlay.show(err.to_string()),
+            }
+        }
+    });
+
+    Ok(Watch { _watcher: watcher })
+}
+
+fn touches(event: &notify::Result<notify::Event>, path: &Path) -> bool {
+    match event {
+        Ok(event) => {
+            matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                && event
+                    .paths
+                    .iter()
+                    .any(|changed| changed.file_name() == path.file_name())
+        }
+        Err(_) => false,
+    }
+}
+
+/// Whether a wav's RIFF header accounts for the whole file, which it only
+/// does once the writer has finished and gone back to fill in the sizes.
+/// Anything else is taken as complete once it stops changing.
+fn is_complete(path: &Path) -> bool {
+    if !input::is_wav(path) {
+        return true;
+    }
+    let Ok(mut file) = File::open(path) else {
+        return false;
+    };
+    let mut header = [0; 8];
+    if file.read_exact(&mut header).is_err() {
+        return false;
+    }
+    if &header[..4] != b"RIFF" {
+        // RF64 and friends: let opening it decide.
+        return true;
+    }
+    let riff_size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    file.metadata()
+        .is_ok_and(|metadata| metadata.len() == riff_size as u64 + 8)
+}
+
+fn reload(path: &Path) -> Result<Box<dyn Source>, Error> {
+    log::info!("reloading {}", path.display());
+    input::open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::{SampleFormat, WavSpec, WavWriter};
+    use std::env;
+    use std::fs;
+
+    fn temp(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("visualizer-{}-{name}", std::process::id()))
+    }
+
+    fn finished(name: &str) -> PathBuf {
+        let path = temp(name);
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for n in 0..100 {
+            writer.write_sample(n as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        path
+    }
+
+    #[test]
+    fn a_finalized_wav_is_complete() {
+        let path = finished("finalized.wav");
+        assert!(is_complete(&path));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn a_wav_still_being_written_is_not() {
+        // Sizes not filled in yet.
+        let path = temp("header-only.wav");
+        fs::write(&path, b"RIFF\0\0\0\0WAVE").unwrap();
+        assert!(!is_complete(&path));
+
+        // Cut off partway through the header.
+        fs::write(&path, b"RIF").unwrap();
+        assert!(!is_complete(&path));
+
+        // Sizes filled in for more than has landed.
+        let whole = finished("whole.wav");
+        let bytes = fs::read(&whole).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 10]).unwrap();
+        assert!(!is_complete(&path));
+        fs::remove_file(path).unwrap();
+        fs::remove_file(whole).unwrap();
+    }
+
+    #[test]
+    fn anything_but_riff_is_left_to_the_decoder() {
+        let path = temp("rf64.wav");
+        fs::write(&path, b"RF64\xff\xff\xff\xffWAVE").unwrap();
+        assert!(is_complete(&path));
+        fs::remove_file(path).unwrap();
+
+        let path = temp("render.flac");
+        fs::write(&path, b"fLaC").unwrap();
+        assert!(is_complete(&path));
+        fs::remove_file(path).unwrap();
+    }
+}
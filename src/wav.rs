@@ -0,0 +1,126 @@
+use crate::error::Error;
+use crate::input::Reader;
+use crate::source::Source;
+use hound::{SampleFormat, WavReader, WavSpec};
+use std::io::Read;
+
+pub type Samples<'a> = Box<dyn Iterator<Item = Result<f32, hound::Error>> + 'a>;
+
+pub fn check_spec(spec: &WavSpec) -> Result<(), Error> {
+    match (spec.sample_format, spec.bits_per_sample) {
+        (SampleFormat::Float, 32) => Ok(()),
+        (SampleFormat::Int, 8 | 16 | 24 | 32) => Ok(()),
+        (format, bits) => Err(Error::Format(format!("{bits}-bit {format:?} wav"))),
+    }
+}
+
+/// Yields every sample in the file as f32 in -1.0..1.0, whatever its sample format.
+pub fn samples_f32<R: Read>(reader: &mut WavReader<R>) -> Samples<'_> {
+    let spec = reader.spec();
+    match spec.sample_format {
+        SampleFormat::Float => Box::new(reader.samples::<f32>()),
+        SampleFormat::Int => {
+            let scale = int_scale(spec.bits_per_sample);
+            Box::new(
+                reader
+                    .samples::<i32>()
+                    .map(move |sample| sample.map(|s| s as f32 * scale)),
+            )
+        }
+    }
+}
+
+// hound sign-extends 8-bit files to -128..127, so every width is symmetric around zero.
+fn int_scale(bits_per_sample: u16) -> f32 {
+    1.0 / (1u64 << (bits_per_sample - 1)) as f32
+}
+
+impl Source for Reader {
+    fn channels(&self) -> usize {
+        self.spec().channels as usize
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.spec().sample_rate
+    }
+
+    fn read(&mut self, len: usize) -> Vec<f32> {
+        samples_f32(self)
+            .take(len)
+            .map_while(|sample| {
+                sample
+                    .map_err(|err| log::warn!("stopped reading wav: {err}"))
+                    .ok()
+            })
+            .collect()
+    }
+
+    fn seek(&mut self, frame: u64) -> Option<u64> {
+        let frame = frame.min(self.duration() as u64);
+        WavReader::seek(self, frame as u32).ok().map(|_| frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::WavWriter;
+    use std::io::Cursor;
+
+    fn spec(sample_format: SampleFormat, bits_per_sample: u16) -> WavSpec {
+        WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample,
+            sample_format,
+        }
+    }
+
+    fn read_back(spec: WavSpec, write: impl Fn(&mut WavWriter<&mut Cursor<Vec<u8>>>)) -> Vec<f32> {
+        let mut bytes = Cursor::new(vec![]);
+        let mut writer = WavWriter::new(&mut bytes, spec).unwrap();
+        write(&mut writer);
+        writer.finalize().unwrap();
+        bytes.set_position(0);
+        let mut reader = WavReader::new(bytes).unwrap();
+        samples_f32(&mut reader).map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn integer_samples_are_scaled_to_full_scale_at_every_width() {
+        let samples = read_back(spec(SampleFormat::Int, 8), |writer| {
+            for sample in [i8::MIN, 0, 64] {
+                writer.write_sample(sample).unwrap();
+            }
+        });
+        assert_eq!(samples, [-1.0, 0.0, 0.5]);
+
+        for bits in [16, 24, 32] {
+            let min = i32::MIN >> (32 - bits);
+            let samples = read_back(spec(SampleFormat::Int, bits), |writer| {
+                for sample in [min, 0, min / -2] {
+                    writer.write_sample(sample).unwrap();
+                }
+            });
+            assert_eq!(samples, [-1.0, 0.0, 0.5], "{bits}-bit");
+        }
+    }
+
+    #[test]
+    fn float_samples_pass_through() {
+        let samples = read_back(spec(SampleFormat::Float, 32), |writer| {
+            for sample in [-1.0f32, 0.25, 1.0] {
+                writer.write_sample(sample).unwrap();
+            }
+        });
+        assert_eq!(samples, [-1.0, 0.25, 1.0]);
+    }
+
+    #[test]
+    fn only_formats_that_can_be_read_are_accepted() {
+        assert!(check_spec(&spec(SampleFormat::Int, 24)).is_ok());
+        assert!(check_spec(&spec(SampleFormat::Float, 32)).is_ok());
+        assert!(check_spec(&spec(SampleFormat::Float, 64)).is_err());
+        assert!(check_spec(&spec(SampleFormat::Int, 12)).is_err());
+    }
+}
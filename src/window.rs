@@ -0,0 +1,146 @@
+use crate::cli::WindowFunction;
+use clap::ValueEnum;
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Four-term Blackman-Harris, with sidelobes 92 dB down.
+const BLACKMAN_HARRIS: [f64; 4] = [0.35875, 0.48829, 0.14128, 0.01168];
+/// Flat top, which reads a sine's level accurately wherever it falls between bins.
+const FLAT_TOP: [f64; 5] = [
+    0.21557895,
+    0.41663158,
+    0.277263158,
+    0.083578947,
+    0.006947368,
+];
+
+/// The window function in use, shared between the keyboard and the analysis thread.
+#[derive(Clone)]
+pub struct Selection {
+    index: Arc<AtomicUsize>,
+}
+
+impl Selection {
+    pub fn new(function: WindowFunction) -> Self {
+        let index = WindowFunction::value_variants()
+            .iter()
+            .position(|&f| f == function)
+            .unwrap_or(0);
+        Selection {
+            index: Arc::new(AtomicUsize::new(index)),
+        }
+    }
+
+    pub fn get(&self) -> WindowFunction {
+        let variants = WindowFunction::value_variants();
+        variants[self.index.load(Ordering::Relaxed) % variants.len()]
+    }
+
+    /// Moves on to the next window function, wrapping around.
+    pub fn next(&self) {
+        let len = WindowFunction::value_variants().len();
+        _ = self
+            .index
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |i| {
+                Some((i + 1) % len)
+            });
+    }
+}
+
+pub fn name(function: WindowFunction) -> String {
+    function
+        .to_possible_value()
+        .map_or(String::new(), |value| value.get_name().to_string())
+}
+
+/// `len` coefficients of `function`, in the periodic form used for spectral
+/// analysis, divided by their mean. Dividing out the coherent gain means a
+/// sine reads the same peak magnitude whichever window is used.
+pub fn coefficients(function: WindowFunction, len: usize, kaiser_beta: f64) -> Vec<f32> {
+    let window = match function {
+        WindowFunction::Rectangular => vec![1.0; len],
+        WindowFunction::Hann => cosine_sum(&[0.5, 0.5], len),
+        WindowFunction::Hamming => cosine_sum(&[0.54, 0.46], len),
+        WindowFunction::BlackmanHarris => cosine_sum(&BLACKMAN_HARRIS, len),
+        WindowFunction::FlatTop => cosine_sum(&FLAT_TOP, len),
+        WindowFunction::Kaiser => {
+            let scale = bessel_i0(kaiser_beta);
+            (0..len)
+                .map(|n| {
+                    let x = 2.0 * n as f64 / len as f64 - 1.0;
+                    bessel_i0(kaiser_beta * (1.0 - x * x).sqrt()) / scale
+                })
+                .collect()
+        }
+    };
+
+    let gain = window.iter().sum::<f64>() / len.max(1) as f64;
+    window.iter().map(|w| (w / gain) as f32).collect()
+}
+
+/// a0 - a1 cos(x) + a2 cos(2x) - ..., with x going once around over `len` samples.
+fn cosine_sum(a: &[f64], len: usize) -> Vec<f64> {
+    (0..len)
+        .map(|n| {
+            let x = 2.0 * PI * n as f64 / len as f64;
+            a.iter()
+                .enumerate()
+                .map(|(k, a)| if k % 2 == 0 { 1.0 } else { -1.0 } * a * (k as f64 * x).cos())
+                .sum()
+        })
+        .collect()
+}
+
+/// The zeroth-order modified Bessel function of the first kind, by its power series.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    for k in 1..50 {
+        term *= (x / (2.0 * k as f64)).powi(2);
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_window_is_periodic_and_keeps_a_sines_level() {
+        for &function in WindowFunction::value_variants() {
+            let window = coefficients(function, 64, 8.6);
+            let mean = window.iter().sum::<f32>() / 64.0;
+            assert!((mean - 1.0).abs() < 1e-5, "{function:?} averages {mean}");
+            for n in 1..32 {
+                assert!((window[n] - window[64 - n]).abs() < 1e-5, "{function:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn hann_runs_from_zero_to_twice_its_mean() {
+        let window = coefficients(WindowFunction::Hann, 8, 0.0);
+        assert!(window[0].abs() < 1e-6);
+        assert!((window[4] - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn bessel_i0_matches_known_values() {
+        assert_eq!(bessel_i0(0.0), 1.0);
+        assert!((bessel_i0(1.0) - 1.2660658777520082).abs() < 1e-12);
+        assert!((bessel_i0(5.0) - 27.239871823604442).abs() < 1e-9);
+    }
+
+    #[test]
+    fn the_selection_wraps_round_to_the_first() {
+        let variants = WindowFunction::value_variants();
+        let selection = Selection::new(variants[variants.len() - 1]);
+        selection.next();
+        assert_eq!(selection.get(), variants[0]);
+    }
+}